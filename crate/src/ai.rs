@@ -1,16 +1,19 @@
-use super::{Board,Loc,Piece};
+use super::{Board,Game,Move,Piece};
+use super::registry::piece_type;
 use rand::prelude::*;
 
-
 #[allow(dead_code)]
-pub fn choose_minimax(start: &Board, white: bool) -> (Loc, Loc) {
-  let scored_moves = start.pieces(white)
-    .flat_map(|(i, _p)|
-              start.moves_from(i).unwrap().into_iter()
-              .map(move |m| (i, m)))
-    .map(|(from, to)| ((from, to), start.move_(from, to)))
-    .filter(|&(_move_, ref board)| !board.is_check(white))
-    .map(|(move_, board)| (move_, minimax(&board, 3, std::i32::MIN, std::i32::MAX, false, white)))
+pub fn choose_minimax(game: &Game) -> Move {
+  let (start, white) = (game.board(), game.white_turn());
+  let mut board = start.clone();
+
+  let scored_moves = start.legal_moves(white).into_iter()
+    .map(|move_| {
+      let undo = board.make_move(move_);
+      let score = minimax(&mut board, 3, std::i32::MIN, std::i32::MAX, false, white);
+      board.unmake_move(undo);
+      (move_, score)
+    })
     .collect::<Vec<_>>();
 
   let max = scored_moves.iter().map(|&(_move_, score)| score).max().expect("No moves available");
@@ -21,33 +24,34 @@ pub fn choose_minimax(start: &Board, white: bool) -> (Loc, Loc) {
     .choose(&mut rand::thread_rng()).expect("No moves available")
 }
 
-fn minimax(start: &Board, depth: usize, mut alpha: i32, mut beta: i32, maximizing_player: bool, ai_white: bool) -> i32 {
-  if depth == 0 { return score_board(start, ai_white); }
+fn minimax(board: &mut Board, depth: usize, mut alpha: i32, mut beta: i32, maximizing_player: bool, ai_white: bool) -> i32 {
+  if depth == 0 { return score_board(board, ai_white); }
 
   let white = !ai_white ^ maximizing_player;
 
-  if start.is_check_mate(white) { return if maximizing_player { std::i32::MIN } else { std::i32::MAX }; }
-  if start.is_stale_mate(white) { return 0; }
+  let moves = board.legal_moves(white);
 
-  let children = start.pieces(white)
-    .flat_map(|(i, _p)|
-              start.moves_from(i).unwrap().into_iter()
-              .map(move |m| (i, m)))
-    .map(|(from, to)| start.move_(from, to))
-    .filter(|board| !board.is_check(white));
+  if moves.is_empty() {
+    if !board.is_check(white) { return 0; }
+    return if maximizing_player { std::i32::MIN } else { std::i32::MAX };
+  }
 
   if maximizing_player {
     let mut value = std::i32::MIN;
-    for board in children {
-      value = value.max(minimax(&board, depth - 1, alpha, beta, !maximizing_player, ai_white));
+    for move_ in moves {
+      let undo = board.make_move(move_);
+      value = value.max(minimax(board, depth - 1, alpha, beta, !maximizing_player, ai_white));
+      board.unmake_move(undo);
       alpha = alpha.max(value);
       if alpha >= beta { break; }
     }
     value
   } else {
     let mut value = std::i32::MAX;
-    for board in children {
-      value = value.min(minimax(&board, depth - 1, alpha, beta, !maximizing_player, ai_white));
+    for move_ in moves {
+      let undo = board.make_move(move_);
+      value = value.min(minimax(board, depth - 1, alpha, beta, !maximizing_player, ai_white));
+      board.unmake_move(undo);
       beta = beta.min(value);
       if alpha >= beta { break; }
     }
@@ -56,13 +60,11 @@ fn minimax(start: &Board, depth: usize, mut alpha: i32, mut beta: i32, maximizin
 }
 
 #[allow(dead_code)]
-pub fn choose_best(start: &Board, white: bool) -> (Loc, Loc) {
-  let scored_moves = start.pieces(white)
-    .flat_map(|(i, _p)|
-              start.moves_from(i).unwrap().into_iter()
-              .map(move |m| (i, m)))
-    .map(|(from, to)| ((from, to), start.move_(from, to)))
-    .filter(|&(_move_, ref board)| !board.is_check(white))
+pub fn choose_best(game: &Game) -> Move {
+  let (start, white) = (game.board(), game.white_turn());
+
+  let scored_moves = start.legal_moves(white).into_iter()
+    .map(|move_| (move_, start.play(move_)))
     .map(|(move_, board)| (move_, score_board(&board, white)))
     .collect::<Vec<_>>();
 
@@ -79,29 +81,32 @@ fn score_board(board: &Board, white: bool) -> i32 {
     board.pieces(!white).map(|(_, p)| score_piece(p)).sum::<i32>()
 }
 
+// The sum of the components, less whatever they'd be counting twice
 fn score_piece(piece: Piece) -> i32 {
-  let mut res = 0;
-
-  if piece.is_king() { res += 1000; }
-  if piece.is_queen() { res += 100; }
-  if piece.is_rook() { res += 70; }
-  if piece.is_knight() { res += 60; }
-  if piece.is_bishop() { res += 50; }
-  if piece.is_pawn() { res += 20; }
-
-  if piece.is_queen() && piece.is_rook() { res -= 20 };
-  if piece.is_queen() && piece.is_bishop() { res -= 20 };
-  if piece.is_queen() && piece.is_pawn() { res -= 10 };
-
-  res
+  piece.components().map(piece_type)
+    .map(|t| t.value - t.overlaps.iter().filter(|&&(c, _)| piece.0 & c != 0).map(|&(_, overlap)| overlap).sum::<i32>())
+    .sum()
 }
 
 #[allow(dead_code)]
-pub fn choose_random(board: &Board, white: bool) -> (Loc, Loc) {
-  board.pieces(white)
-    .flat_map(|(i, _p)|
-              board.moves_from(i).unwrap().into_iter()
-              .map(move |m| (i, m)))
-    .filter(|&(from, to)| !board.move_(from, to).is_check(white))
+pub fn choose_random(game: &Game) -> Move {
+  game.legal_moves().into_iter()
     .choose(&mut rand::thread_rng()).expect("No moves available")
 }
+
+#[cfg(test)]
+mod test {
+  use super::score_piece;
+  use super::super::{Piece,WHITE,BLACK,KING,QUEEN,ROOK,BISHOP,KNIGHT,PAWN,NIGHTRIDER};
+
+  #[test]
+  pub fn piece_scores() {
+    assert_eq!(score_piece(Piece(WHITE | QUEEN)), 100);
+    assert_eq!(score_piece(Piece(BLACK | KING | QUEEN)), 1100);
+    assert_eq!(score_piece(Piece(WHITE | QUEEN | ROOK)), 150);
+    assert_eq!(score_piece(Piece(WHITE | QUEEN | PAWN)), 110);
+    assert_eq!(score_piece(Piece(WHITE | QUEEN | ROOK | BISHOP)), 180);
+    assert_eq!(score_piece(Piece(WHITE | ROOK | KNIGHT)), 130);
+    assert_eq!(score_piece(Piece(WHITE | KNIGHT | NIGHTRIDER)), 120);
+  }
+}
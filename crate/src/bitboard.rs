@@ -0,0 +1,347 @@
+use super::{Board,Castle,Geometry,Loc,Piece,RuleSet,WHITE,BLACK,COLOR_MASK,KING,QUEEN,ROOK,BISHOP,KNIGHT,PAWN};
+
+// Bit `i` of every mask stands for `Loc(i)`, so iterating the set bits from
+// the bottom up visits squares in the same order as sorting `Loc`s. Only the
+// standard board fits in a mask.
+fn bit(loc: Loc) -> u64 {
+  1 << loc.0
+}
+
+fn locs(mut bits: u64) -> Vec<Loc> {
+  let mut locs = Vec::with_capacity(bits.count_ones() as usize);
+
+  while bits != 0 {
+    locs.push(Loc(bits.trailing_zeros() as i32));
+    bits &= bits - 1;
+  }
+
+  locs
+}
+
+const fn leaper_table(ds: &[(i32, i32)]) -> [u64; 64] {
+  let mut table = [0; 64];
+
+  let mut i = 0;
+  while i < 64 {
+    let (x, y) = ((i % 8) as i32, (i / 8) as i32);
+
+    let mut j = 0;
+    while j < ds.len() {
+      let (nx, ny) = (x + ds[j].0, y + ds[j].1);
+      if nx >= 0 && nx <= 7 && ny >= 0 && ny <= 7 {
+        table[i] |= 1 << (ny * 8 + nx);
+      }
+      j += 1;
+    }
+
+    i += 1;
+  }
+
+  table
+}
+
+const KNIGHT_MOVES: [u64; 64] = leaper_table(&[(1, 2), (2, 1), (-1, 2), (2, -1), (1, -2), (-2, 1), (-1, -2), (-2, -1)]);
+const KING_MOVES: [u64; 64] = leaper_table(&[(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (-1, 1), (1, -1), (-1, -1)]);
+const WHITE_PAWN_ATTACKS: [u64; 64] = leaper_table(&[(-1, -1), (1, -1)]);
+const BLACK_PAWN_ATTACKS: [u64; 64] = leaper_table(&[(-1, 1), (1, 1)]);
+
+// The first four directions run towards higher squares and the last four
+// towards lower ones, which decides which end of the blockers is nearest.
+const DIRECTIONS: [(i32, i32); 8] = [(1, 0), (0, 1), (1, 1), (-1, 1), (-1, 0), (0, -1), (-1, -1), (1, -1)];
+const ROOK_DIRECTIONS: [usize; 4] = [0, 1, 4, 5];
+const BISHOP_DIRECTIONS: [usize; 4] = [2, 3, 6, 7];
+
+// Every square from each square to the edge of the board, one table per
+// direction.
+const fn ray_table() -> [[u64; 64]; 8] {
+  let mut table = [[0; 64]; 8];
+
+  let mut d = 0;
+  while d < 8 {
+    let (dx, dy) = DIRECTIONS[d];
+
+    let mut i = 0;
+    while i < 64 {
+      let (mut x, mut y) = ((i % 8) as i32 + dx, (i / 8) as i32 + dy);
+      while x >= 0 && x <= 7 && y >= 0 && y <= 7 {
+        table[d][i] |= 1 << (y * 8 + x);
+        x += dx;
+        y += dy;
+      }
+      i += 1;
+    }
+
+    d += 1;
+  }
+
+  table
+}
+
+const RAYS: [[u64; 64]; 8] = ray_table();
+
+// Everything along a ray up to and including the first occupied square.
+fn ray_attacks(d: usize, loc: Loc, occupied: u64) -> u64 {
+  let ray = RAYS[d][loc.0 as usize];
+  let blockers = ray & occupied;
+
+  if blockers == 0 { return ray; }
+
+  let first = if d < 4 { blockers.trailing_zeros() } else { 63 - blockers.leading_zeros() };
+  ray ^ RAYS[d][first as usize]
+}
+
+fn sliding_attacks(directions: &[usize], loc: Loc, occupied: u64) -> u64 {
+  directions.iter().fold(0, |attacks, &d| attacks | ray_attacks(d, loc, occupied))
+}
+
+// Only the orthodox pieces get masks
+const TYPES: [u16; 6] = [KING, QUEEN, ROOK, BISHOP, KNIGHT, PAWN];
+
+// The same position as a `Board`, but with one mask per component type and
+// per color instead of a piece per square. A hybrid sets a bit in the mask of
+// each of its components. `Board` generates moves and looks for check with
+// these whenever a position fits in them.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Bitboards {
+  components: [u64; 6],
+  white: u64,
+  black: u64,
+  castling: u8,
+  castles: [Castle; 4],
+  en_passant: Option<Loc>,
+  rules: RuleSet,
+}
+
+impl Bitboards {
+  pub fn new(board: &Board) -> Option<Self> {
+    if board.geometry != Geometry::STANDARD { return None; }
+    let orthodox = TYPES.iter().fold(COLOR_MASK, |mask, &t| mask | t);
+    if board.pieces.iter().any(|p| p.0 & !orthodox != 0) { return None; }
+
+    let mut bitboards = Bitboards{
+      components: [0; 6],
+      white: 0,
+      black: 0,
+      castling: board.castling,
+      castles: board.castles,
+      en_passant: board.en_passant,
+      rules: board.rules,
+    };
+
+    for (i, &piece) in board.pieces.iter().enumerate() {
+      let loc = Loc(i as i32);
+      if piece.is_empty() { continue; }
+
+      if piece.is_white() { bitboards.white |= bit(loc) } else { bitboards.black |= bit(loc) }
+      for (t, &component) in TYPES.iter().enumerate() {
+        if piece.0 & component != 0 { bitboards.components[t] |= bit(loc); }
+      }
+    }
+
+    Some(bitboards)
+  }
+
+  fn occupied(&self) -> u64 {
+    self.white | self.black
+  }
+
+  fn color(&self, white: bool) -> u64 {
+    if white { self.white } else { self.black }
+  }
+
+  pub fn piece(&self, loc: Loc) -> Piece {
+    let b = bit(loc);
+    if self.occupied() & b == 0 { return Piece::EMPTY; }
+
+    let color = if self.white & b != 0 { WHITE } else { BLACK };
+    TYPES.iter().zip(self.components.iter())
+      .filter(|&(_, &mask)| mask & b != 0)
+      .fold(Piece(color), |piece, (&component, _)| Piece(piece.0 | component))
+  }
+
+  pub fn moves_from(&self, loc: Loc) -> Option<Vec<Loc>> {
+    let piece = self.piece(loc);
+
+    if piece.is_empty() { return None };
+
+    let mut dests = self.piece_moves(loc, piece);
+
+    if piece.is_king() {
+      for castle in self.castles.iter().filter(|c| c.king_from == loc) {
+        if self.can_castle(castle) {
+          dests |= bit(castle.king_to);
+        }
+      }
+    }
+
+    Some(locs(dests))
+  }
+
+  // As `Board::piece_moves_from`, leaving out castling
+  pub(crate) fn piece_moves_from(&self, loc: Loc, piece: Piece) -> Vec<Loc> {
+    locs(self.piece_moves(loc, piece))
+  }
+
+  pub fn split_moves_from(&self, loc: Loc, component: u16) -> Option<Vec<Loc>> {
+    let piece = self.piece(loc);
+
+    if !self.rules.splits || !piece.is_hybrid() || (piece.0 & component) == 0 { return None };
+
+    Some(locs(self.piece_moves(loc, Piece(piece.color() | component))))
+  }
+
+  pub fn is_check(&self, white: bool) -> bool {
+    let kings = self.components[0] & self.color(white);
+    locs(kings).into_iter().any(|loc| self.is_square_attacked(loc, !white))
+  }
+
+  pub fn is_square_attacked(&self, loc: Loc, by_white: bool) -> bool {
+    self.attackers(loc, by_white) != 0
+  }
+
+  pub fn attackers_of(&self, loc: Loc, white: bool) -> Vec<Loc> {
+    locs(self.attackers(loc, white))
+  }
+
+  // Attack patterns are symmetric, so the pieces attacking `loc` are the ones
+  // standing where the same pattern from `loc` lands. Pawns are the
+  // exception and use the other color's table.
+  fn attackers(&self, loc: Loc, white: bool) -> u64 {
+    let i = loc.0 as usize;
+    let occupied = self.occupied();
+    let [kings, queens, rooks, bishops, knights, pawns] = self.components;
+    let pawn_attacks = if white { BLACK_PAWN_ATTACKS[i] } else { WHITE_PAWN_ATTACKS[i] };
+
+    let attackers = (KING_MOVES[i] & kings)
+      | (KNIGHT_MOVES[i] & knights)
+      | (pawn_attacks & pawns)
+      | (sliding_attacks(&ROOK_DIRECTIONS, loc, occupied) & (rooks | queens))
+      | (sliding_attacks(&BISHOP_DIRECTIONS, loc, occupied) & (bishops | queens));
+
+    attackers & self.color(white)
+  }
+
+  fn can_castle(&self, castle: &Castle) -> bool {
+    if self.castling & castle.right == 0 { return false; }
+
+    let color = if castle.white { WHITE } else { BLACK };
+    if self.piece(castle.king_from) != Piece(color | KING) { return false; }
+    if self.piece(castle.rook_from) != Piece(color | ROOK) { return false; }
+
+    let path = castle.king_path().chain(castle.rook_path()).fold(0, |path, loc| path | bit(loc));
+    if path & !bit(castle.king_from) & !bit(castle.rook_from) & self.occupied() != 0 { return false; }
+
+    !castle.king_path().any(|loc| self.is_square_attacked(loc, !castle.white))
+  }
+
+  // The union of what each component could do from `loc` on its own, minus
+  // any merges the rules forbid.
+  fn piece_moves(&self, loc: Loc, piece: Piece) -> u64 {
+    let i = loc.0 as usize;
+    let occupied = self.occupied();
+    let mut dests = 0;
+
+    if piece.is_pawn() {
+      let (forward, starting_row, attacks) = if piece.is_white() {
+        (-8, 6, WHITE_PAWN_ATTACKS[i])
+      } else {
+        (8, 1, BLACK_PAWN_ATTACKS[i])
+      };
+
+      let single = loc.0 + forward;
+      if (0..64).contains(&single) && occupied & bit(Loc(single)) == 0 {
+        dests |= bit(Loc(single));
+
+        let double = single + forward;
+        if Geometry::STANDARD.y(loc) == starting_row && occupied & bit(Loc(double)) == 0 {
+          dests |= bit(Loc(double));
+        }
+      }
+
+      dests |= attacks & occupied;
+
+      if let Some(en_passant) = self.en_passant {
+        let victim = Geometry::STANDARD.loc(Geometry::STANDARD.x(en_passant), Geometry::STANDARD.y(loc));
+        if attacks & bit(en_passant) & !occupied != 0 && self.color(!piece.is_white()) & bit(victim) != 0 {
+          dests |= bit(en_passant);
+        }
+      }
+    }
+    if piece.is_rook() || piece.is_queen() {
+      dests |= sliding_attacks(&ROOK_DIRECTIONS, loc, occupied);
+    }
+    if piece.is_bishop() || piece.is_queen() {
+      dests |= sliding_attacks(&BISHOP_DIRECTIONS, loc, occupied);
+    }
+    if piece.is_king() {
+      dests |= KING_MOVES[i];
+    }
+    if piece.is_knight() {
+      dests |= KNIGHT_MOVES[i];
+    }
+
+    for to in locs(dests & self.color(piece.is_white())) {
+      if !self.rules.allows_merge(piece, self.piece(to)) {
+        dests &= !bit(to);
+      }
+    }
+
+    dests
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Bitboards,TYPES};
+  use super::super::{Board,Geometry,Loc,Piece,RuleSet,WHITE,ROOK,KNIGHT,PAWN};
+
+  fn assert_same_moves(board: &Board) {
+    let bitboards = Bitboards::new(board).unwrap();
+    assert!(bitboards.is_check(true) == board.pieces(true).any(|(loc, p)| p.is_king() && board.is_square_attacked(loc, false)));
+    assert!(bitboards.is_check(false) == board.pieces(false).any(|(loc, p)| p.is_king() && board.is_square_attacked(loc, true)));
+
+    for i in 0..64 {
+      let loc = Loc(i);
+      assert!(bitboards.piece(loc) == board.piece(loc));
+      assert!(bitboards.moves_from(loc) == board.moves_from(loc), "moves from {}", i);
+      assert!(bitboards.attackers_of(loc, true) == board.attackers_of(loc, true), "attackers of {}", i);
+      assert!(bitboards.attackers_of(loc, false) == board.attackers_of(loc, false), "attackers of {}", i);
+
+      for &component in &TYPES {
+        assert!(bitboards.split_moves_from(loc, component) == board.split_moves_from(loc, component), "split moves from {}", i);
+      }
+    }
+  }
+
+  #[test]
+  pub fn matches_board() {
+    let starts = [Board::with_rules(RuleSet::CLASSIC), Board::with_rules(RuleSet::STRICT), Board::chess960(314, RuleSet::CLASSIC)];
+    for start in &starts {
+      let mut board = start.clone();
+      let mut white = true;
+
+      // A fixed but varied walk through a game, checking every position on
+      // the way.
+      for ply in 0..80 {
+        assert_same_moves(&board);
+
+        let moves = board.legal_moves(white);
+        if moves.is_empty() { break; }
+        board = board.play(moves[(ply * 7 + 3) % moves.len()]);
+        white = !white;
+      }
+    }
+
+    let mut board = Board::empty();
+    board.pieces[3 + 3*8] = Piece(WHITE | ROOK | KNIGHT | PAWN);
+    board.pieces[4 + 1*8] = Piece::BLACK_PAWN;
+    board.pieces[3 + 6*8] = Piece::WHITE_PAWN;
+    board.pieces[7 + 7*8] = Piece::WHITE_KING;
+    board.pieces[0 + 0*8] = Piece::BLACK_KING;
+    board = board.move_(Loc(4 + 1*8), Loc(4 + 3*8));
+    assert_same_moves(&board);
+
+    assert!(Bitboards::new(&Board::starting(Geometry::WIDE, RuleSet::default()).unwrap()).is_none());
+    assert!(Bitboards::new(&Board::from_fen("4k3/8/8/8/8/8/8/2C1K3 w - -").unwrap()).is_none());
+  }
+}
@@ -0,0 +1,338 @@
+use std::error::Error;
+use std::fmt;
+
+use super::{component_letter,letter_component,Board,Castle,Geometry,Piece,CASTLING_RIGHTS,WHITE,BLACK,COLOR_MASK};
+use super::{WHITE_KINGSIDE,WHITE_QUEENSIDE,BLACK_KINGSIDE,BLACK_QUEENSIDE};
+use super::geometry::MAX_SIZE;
+
+// FEN, extended so that a hybrid is written as its component letters in
+// brackets, e.g. `[RN]` for a white rook+knight or `[qb]` for a black
+// queen+bishop. Letters always come in KQRBNP order. The halfmove and
+// fullmove fields are optional since a board doesn't track them.
+//
+// The board's size comes from the piece placement: as many ranks as there
+// are, each as wide as the first, so `10` can stand for ten empty squares.
+//
+// Castling rights can also name the rook's file, Shredder-FEN style, for
+// Chess960 positions where K, Q, k or q would be ambiguous: `KQkq` always
+// means the rook furthest out on each side.
+
+const CASTLING_LETTERS: [(char, u8); 4] = [
+  ('K', WHITE_KINGSIDE),
+  ('Q', WHITE_QUEENSIDE),
+  ('k', BLACK_KINGSIDE),
+  ('q', BLACK_QUEENSIDE),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FenError {
+  MissingField(&'static str),
+  TooManyFields,
+  // Width, then height
+  BoardSize(usize, usize),
+  // Numbered like the ranks themselves, the top one first
+  RankLength(usize),
+  UnknownPiece(char),
+  BadHybrid(String),
+  SideToMove(String),
+  Castling(String),
+  EnPassant(String),
+  Clock(String),
+}
+
+impl fmt::Display for FenError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      FenError::MissingField(field) => write!(f, "missing the {} field", field),
+      FenError::TooManyFields => write!(f, "too many fields"),
+      FenError::BoardSize(width, height) => write!(f, "{}x{} boards aren't supported", width, height),
+      FenError::RankLength(rank) => write!(f, "rank {} isn't as wide as the first", rank),
+      FenError::UnknownPiece(c) => write!(f, "unknown piece '{}'", c),
+      FenError::BadHybrid(ref group) => write!(f, "bad hybrid '{}'", group),
+      FenError::SideToMove(ref side) => write!(f, "side to move should be 'w' or 'b', not '{}'", side),
+      FenError::Castling(ref castling) => write!(f, "bad castling rights '{}'", castling),
+      FenError::EnPassant(ref square) => write!(f, "bad en passant square '{}'", square),
+      FenError::Clock(ref clock) => write!(f, "bad move counter '{}'", clock),
+    }
+  }
+}
+
+impl Error for FenError {}
+
+fn parse_hybrid(group: &str) -> Result<Piece, FenError> {
+  let bad = || FenError::BadHybrid(format!("[{}]", group));
+
+  if group.is_empty() { return Err(bad()); }
+
+  let white = group.chars().all(|c| c.is_ascii_uppercase());
+  let black = group.chars().all(|c| c.is_ascii_lowercase());
+  if !white && !black { return Err(bad()); }
+
+  let mut piece = Piece(if white { WHITE } else { BLACK });
+  for c in group.chars() {
+    let component = letter_component(c).ok_or(FenError::UnknownPiece(c))?;
+    if piece.0 & component != 0 { return Err(bad()); }
+    piece = Piece(piece.0 | component);
+  }
+
+  Ok(piece)
+}
+
+fn parse_rank(rank: &str, number: usize) -> Result<Vec<Piece>, FenError> {
+  let mut pieces = vec![];
+  let mut chars = rank.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    let piece = if let Some(digit) = c.to_digit(10) {
+      if digit == 0 { return Err(FenError::RankLength(number)); }
+
+      // Anything wider than the biggest board is an error either way, so the
+      // count stops there instead of overflowing
+      let mut skip = digit as usize;
+      while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        skip = (skip * 10 + digit as usize).min(MAX_SIZE + 1);
+        chars.next();
+      }
+      pieces.resize(pieces.len() + skip, Piece::EMPTY);
+      continue;
+    } else if c == '[' {
+      let mut group = String::new();
+      let mut closed = false;
+      for c in chars.by_ref() {
+        if c == ']' { closed = true; break; }
+        group.push(c);
+      }
+
+      if !closed { return Err(FenError::BadHybrid(format!("[{}", group))); }
+      parse_hybrid(&group)?
+    } else {
+      let component = letter_component(c).ok_or(FenError::UnknownPiece(c))?;
+      Piece(if c.is_ascii_uppercase() { WHITE } else { BLACK } | component)
+    };
+
+    pieces.push(piece);
+  }
+
+  Ok(pieces)
+}
+
+impl Piece {
+  // As in a FEN's piece placement, like `N` or `[qn]`
+  pub fn to_fen(self) -> String {
+    let letters = self.components()
+      .map(component_letter)
+      .map(|c| if self.is_white() { c } else { c.to_ascii_lowercase() })
+      .collect::<String>();
+
+    if self.is_hybrid() { format!("[{}]", letters) } else { letters }
+  }
+}
+
+// One rank written the same way as in a FEN's piece placement, for giving a
+// starting back rank. Only the components are kept, so either case will do.
+pub fn parse_back_rank(rank: &str) -> Result<Vec<u16>, FenError> {
+  let pieces = parse_rank(rank.trim(), 1)?;
+
+  Ok(pieces.iter().map(|piece| piece.0 & !COLOR_MASK).collect())
+}
+
+impl Board {
+  pub fn from_fen(fen: &str) -> Result<Self, FenError> {
+    let mut fields = fen.split_whitespace();
+    let placement = fields.next().ok_or(FenError::MissingField("piece placement"))?;
+    let side = fields.next().ok_or(FenError::MissingField("side to move"))?;
+    let castling = fields.next().ok_or(FenError::MissingField("castling"))?;
+    let en_passant = fields.next().ok_or(FenError::MissingField("en passant"))?;
+    for clock in fields.by_ref().take(2) {
+      if clock.parse::<u32>().is_err() { return Err(FenError::Clock(clock.to_owned())); }
+    }
+    if fields.next().is_some() { return Err(FenError::TooManyFields); }
+
+    let ranks = placement.split('/').collect::<Vec<_>>();
+    let mut pieces = vec![];
+    for (y, rank) in ranks.iter().enumerate() {
+      let rank = parse_rank(rank, ranks.len() - y)?;
+      if rank.len() > MAX_SIZE { return Err(FenError::BoardSize(rank.len(), ranks.len())); }
+      if y > 0 && rank.len() != pieces.len() / y { return Err(FenError::RankLength(ranks.len() - y)); }
+      pieces.extend(rank);
+    }
+
+    let width = pieces.len() / ranks.len();
+    let geometry = Geometry::new(width, ranks.len()).ok_or(FenError::BoardSize(width, ranks.len()))?;
+    let mut board = Board::empty_with_geometry(geometry);
+    board.pieces = pieces;
+
+    board.white_turn = match side {
+      "w" => true,
+      "b" => false,
+      _ => return Err(FenError::SideToMove(side.to_owned())),
+    };
+
+    board.set_castling_fen(castling)?;
+    board.set_en_passant_fen(en_passant)?;
+
+    board.hash = board.compute_hash();
+    Ok(board)
+  }
+
+  pub fn to_fen(&self) -> String {
+    let ranks = self.pieces.chunks(self.geometry.width()).map(|rank| {
+      let mut fen = String::new();
+      let mut empty = 0;
+
+      for &piece in rank {
+        if piece.is_empty() {
+          empty += 1;
+          continue;
+        }
+        if empty > 0 { fen += &empty.to_string(); }
+        empty = 0;
+        fen += &piece.to_fen();
+      }
+      if empty > 0 { fen += &empty.to_string(); }
+
+      fen
+    }).collect::<Vec<_>>();
+
+    format!("{} {} {} {}",
+            ranks.join("/"),
+            if self.white_turn { "w" } else { "b" },
+            self.castling_fen(),
+            self.en_passant.map_or("-".to_owned(), |loc| self.geometry.name(loc)))
+  }
+
+  // Rooks that don't start outermost are given by their file, as in
+  // Shredder-FEN
+  pub(crate) fn castling_fen(&self) -> String {
+    let castling = CASTLING_LETTERS.iter().zip(self.castles.iter())
+      .filter(|&(&(_, right), _)| self.castling & right != 0)
+      .map(|(&(letter, right), castle)| {
+        if self.outer_castle(right).unwrap_or_else(|| Castle::usual(right, self.geometry)) == *castle {
+          letter
+        } else {
+          let file = b'a' + self.geometry.x(castle.rook_from) as u8;
+          (if castle.white { file.to_ascii_uppercase() } else { file }) as char
+        }
+      })
+      .collect::<String>();
+
+    if castling.is_empty() { "-".to_owned() } else { castling }
+  }
+
+  // Doesn't update the hash
+  pub(crate) fn set_castling_fen(&mut self, castling: &str) -> Result<(), FenError> {
+    self.castling = 0;
+    if castling == "-" { return Ok(()); }
+
+    for c in castling.chars() {
+      // Rights without the king and rook to back them up are kept, and
+      // assumed to be for the usual squares.
+      let castle = match CASTLING_LETTERS.iter().find(|&&(letter, _)| letter == c) {
+        Some(&(_, right)) => self.outer_castle(right).or_else(|| Some(Castle::usual(right, self.geometry))),
+        None if c.is_ascii_uppercase() => self.castle_with_rook(true, (c as u8 - b'A') as usize),
+        None if c.is_ascii_lowercase() => self.castle_with_rook(false, (c as u8 - b'a') as usize),
+        None => None,
+      };
+
+      let castle = castle
+        .filter(|castle| self.castling & castle.right == 0)
+        .ok_or_else(|| FenError::Castling(castling.to_owned()))?;
+      let i = CASTLING_RIGHTS.iter().position(|&right| right == castle.right).unwrap();
+      self.castles[i] = castle;
+      self.castling |= castle.right;
+    }
+
+    Ok(())
+  }
+
+  // Needs the side to move set first. Doesn't update the hash.
+  pub(crate) fn set_en_passant_fen(&mut self, en_passant: &str) -> Result<(), FenError> {
+    self.en_passant = None;
+    if en_passant == "-" { return Ok(()); }
+
+    let row = if self.white_turn { 2 } else { self.geometry.height() - 3 };
+    let loc = self.geometry.parse(en_passant)
+      .filter(|&loc| self.geometry.y(loc) == row)
+      .ok_or_else(|| FenError::EnPassant(en_passant.to_owned()))?;
+    self.en_passant = Some(loc);
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{parse_back_rank,FenError};
+  use super::super::{Board,Geometry,Piece,WHITE,BLACK,KING,QUEEN,ROOK,BISHOP,KNIGHT};
+  use super::super::geometry::loc;
+
+  #[test]
+  pub fn fresh() {
+    let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -";
+
+    assert_eq!(Board::fresh().to_fen(), fen);
+    assert!(Board::from_fen(fen).unwrap() == Board::fresh());
+    assert!(Board::from_fen(&format!("{} 0 1", fen)).unwrap() == Board::fresh());
+  }
+
+  #[test]
+  pub fn round_trip() {
+    let fen = "r3k2r/pp1[QB]1ppp/8/2[rn]1pP2/8/8/PPP2[NP]PP/R3K2R w Kq e6";
+    let board = Board::from_fen(fen).unwrap();
+
+    assert!(board.piece(loc("d7")) == Piece(WHITE | QUEEN | BISHOP));
+    assert!(board.piece(loc("c5")) == Piece(BLACK | ROOK | KNIGHT));
+    assert!(board.en_passant == Some(loc("e6")));
+    assert_eq!(board.to_fen(), fen);
+
+    let board = board.move_(loc("f5"), loc("e6"));
+    assert_eq!(board.to_fen(), "r3k2r/pp1[QB]1ppp/4P3/2[rn]5/8/8/PPP2[NP]PP/R3K2R b Kq -");
+    assert!(Board::from_fen(&board.to_fen()).unwrap() == board);
+  }
+
+  #[test]
+  pub fn other_sizes() {
+    // A Capablanca chess position after f4, with the archbishop and chancellor
+    // as hybrids
+    let fen = "r[bn]bqkb[rn]n1r/pppppppppp/10/10/5P4/10/PPPPP1PPPP/R[BN]BQKB[RN]N1R b KQkq f3";
+    let board = Board::from_fen(fen).unwrap();
+    assert!(board.geometry() == Geometry::WIDE);
+    assert!(board.en_passant == Geometry::WIDE.parse("f3"));
+    assert_eq!(board.to_fen(), fen);
+
+    let fen = "4k3/8/8/8/p7/8/8/8/8/8/8/4K3 w - a10";
+    let board = Board::from_fen(fen).unwrap();
+    assert!(board.geometry() == Geometry::new(8, 12).unwrap());
+    assert_eq!(board.to_fen(), fen);
+  }
+
+  #[test]
+  pub fn back_rank() {
+    assert!(parse_back_rank("RNB[QK]1BNR").unwrap() == [ROOK, KNIGHT, BISHOP, QUEEN | KING, 0, BISHOP, KNIGHT, ROOK]);
+    assert_eq!(parse_back_rank("R8K").unwrap().len(), 10);
+    assert!(parse_back_rank(" rnbqkbnr ").unwrap() == parse_back_rank("RNBQKBNR").unwrap());
+    assert_eq!(parse_back_rank("RNBQ0KBNR").err(), Some(FenError::RankLength(1)));
+  }
+
+  #[test]
+  pub fn errors() {
+    assert_eq!(Board::from_fen("8/8/8/8/8/8/8/8 w -").err(), Some(FenError::MissingField("en passant")));
+    assert_eq!(Board::from_fen("8/8/8 w - -").err(), Some(FenError::BoardSize(8, 3)));
+    assert_eq!(Board::from_fen("17/17/17/17 w - -").err(), Some(FenError::BoardSize(17, 4)));
+    assert_eq!(Board::from_fen("99999999999999999999999/8 w - -").err(), Some(FenError::BoardSize(17, 2)));
+    assert_eq!(Board::from_fen("8/8/8/8/8/8/8/4K99999999999999999999 w - -").err(), Some(FenError::BoardSize(22, 8)));
+    assert_eq!(Board::from_fen("8/8/8/8/8/8/7/8 w - -").err(), Some(FenError::RankLength(2)));
+    assert_eq!(Board::from_fen("8/8/8/8/8/8/8/[RN]8 w - -").err(), Some(FenError::RankLength(1)));
+    assert_eq!(Board::from_fen("8/8/8/8/8/8/8/[Rn]7 w - -").err(), Some(FenError::BadHybrid("[Rn]".to_owned())));
+    assert_eq!(Board::from_fen("8/8/8/8/8/8/8/[RR]7 w - -").err(), Some(FenError::BadHybrid("[RR]".to_owned())));
+    assert_eq!(Board::from_fen("8/8/8/8/8/8/8/[RN7 w - -").err(), Some(FenError::BadHybrid("[RN7".to_owned())));
+    assert_eq!(Board::from_fen("8/8/8/8/8/8/8/X7 w - -").err(), Some(FenError::UnknownPiece('X')));
+    assert_eq!(Board::from_fen("8/8/8/8/8/8/8/8 x - -").err(), Some(FenError::SideToMove("x".to_owned())));
+    assert_eq!(Board::from_fen("8/8/8/8/8/8/8/8 w KK -").err(), Some(FenError::Castling("KK".to_owned())));
+    assert_eq!(Board::from_fen("8/8/8/8/8/8/8/R7 w A -").err(), Some(FenError::Castling("A".to_owned())));
+    assert_eq!(Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w Ae -").err(), Some(FenError::Castling("Ae".to_owned())));
+    assert_eq!(Board::from_fen("8/8/8/8/8/8/8/8 w - e4").err(), Some(FenError::EnPassant("e4".to_owned())));
+    assert_eq!(Board::from_fen("8/8/8/8/8/8/8/8 w - - x 1").err(), Some(FenError::Clock("x".to_owned())));
+    assert_eq!(Board::from_fen("8/8/8/8/8/8/8/8 w - - 0 1 2").err(), Some(FenError::TooManyFields));
+  }
+}
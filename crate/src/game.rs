@@ -0,0 +1,220 @@
+use super::{Board,Move,Piece,RuleSet,PAWN};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Draw {
+  Stalemate,
+  ThreefoldRepetition,
+  FiftyMoveRule,
+  InsufficientMaterial,
+}
+
+impl Draw {
+  pub fn description(self) -> &'static str {
+    match self {
+      Draw::Stalemate => "Stalemate",
+      Draw::ThreefoldRepetition => "Draw by threefold repetition",
+      Draw::FiftyMoveRule => "Draw by the fifty-move rule",
+      Draw::InsufficientMaterial => "Draw by insufficient material",
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+  // Holds whether white is the winner
+  Checkmate(bool),
+  Draw(Draw),
+}
+
+#[derive(Clone)]
+pub struct Game {
+  // The position the game started from
+  start: Board,
+  board: Board,
+  moves: Vec<Move>,
+  captured: Vec<Piece>,
+  result: Option<GameResult>,
+  // Hashes of every position reached so far, including the current one
+  positions: Vec<u64>,
+  // Plies since the last capture or pawn move
+  halfmove_clock: u32,
+}
+
+impl Game {
+  pub fn new(rules: RuleSet) -> Self {
+    Self::from_board(Board::with_rules(rules), true)
+  }
+
+  pub fn from_board(mut board: Board, white_turn: bool) -> Self {
+    // Boards set up by hand may not have an up to date hash
+    board.white_turn = white_turn;
+    board.hash = board.compute_hash();
+
+    let mut game = Game{
+      start: board.clone(),
+      board,
+      moves: vec![],
+      captured: vec![],
+      result: None,
+      positions: vec![],
+      halfmove_clock: 0,
+    };
+    game.positions.push(game.board.hash());
+    game.result = game.compute_result();
+    game
+  }
+
+  pub fn start(&self) -> &Board { &self.start }
+  pub fn board(&self) -> &Board { &self.board }
+  pub fn white_turn(&self) -> bool { self.board.white_turn() }
+  pub fn moves(&self) -> &[Move] { &self.moves }
+  pub fn last_move(&self) -> Option<Move> { self.moves.last().cloned() }
+  pub fn result(&self) -> Option<GameResult> { self.result }
+  pub fn halfmove_clock(&self) -> u32 { self.halfmove_clock }
+
+  // Pieces of the given color that have been captured so far
+  pub fn captured<'a>(&'a self, white: bool) -> impl Iterator<Item=Piece> + 'a {
+    self.captured.iter().cloned().filter(move |p| p.is_white() == white)
+  }
+
+  pub fn legal_moves(&self) -> Vec<Move> {
+    if self.result.is_some() { return vec![]; }
+
+    self.board.legal_moves(self.white_turn())
+  }
+
+  pub fn play(&mut self, move_: Move) {
+    assert!(self.result.is_none(), "The game is over");
+    assert!(self.board.piece(move_.from).is_white() == self.white_turn(), "Not that side's turn");
+
+    let captured = self.board.captured_by(move_);
+    let pawn_move = match move_.component {
+      Some(component) => component == PAWN,
+      None => self.board.piece(move_.from).is_pawn(),
+    };
+
+    if captured.is_some() || pawn_move {
+      self.halfmove_clock = 0;
+    } else {
+      self.halfmove_clock += 1;
+    }
+    self.captured.extend(captured);
+
+    self.board.make_move(move_);
+    self.moves.push(move_);
+    self.positions.push(self.board.hash());
+
+    self.result = self.compute_result();
+  }
+
+  fn compute_result(&self) -> Option<GameResult> {
+    let white_turn = self.white_turn();
+
+    if self.board.is_check_mate(white_turn) {
+      Some(GameResult::Checkmate(!white_turn))
+    } else if self.board.is_stale_mate(white_turn) {
+      Some(GameResult::Draw(Draw::Stalemate))
+    } else if self.board.is_insufficient_material() {
+      Some(GameResult::Draw(Draw::InsufficientMaterial))
+    } else if self.repetitions() >= 3 {
+      Some(GameResult::Draw(Draw::ThreefoldRepetition))
+    } else if self.halfmove_clock >= 100 {
+      Some(GameResult::Draw(Draw::FiftyMoveRule))
+    } else {
+      None
+    }
+  }
+
+  // How many times the current position has come up
+  fn repetitions(&self) -> usize {
+    let current = self.positions.last().unwrap();
+
+    self.positions.iter().filter(|&p| p == current).count()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Draw,Game,GameResult};
+  use super::super::{Board,Loc,Piece,MoveKind,RuleSet};
+
+  #[test]
+  pub fn play_to_mate() {
+    let mut board = Board::empty();
+
+    board.pieces[7 + 0*8] = Piece::BLACK_KING;
+    board.pieces[0 + 3*8] = Piece::BLACK_PAWN;
+    board.pieces[0 + 7*8] = Piece::WHITE_ROOK;
+    board.pieces[1 + 6*8] = Piece::WHITE_ROOK;
+    board.pieces[2 + 5*8] = Piece::WHITE_KING;
+
+    let mut game = Game::from_board(board, true);
+    assert!(game.result().is_none());
+
+    let moves = [
+      (0 + 7*8, 0 + 3*8),
+      (7 + 0*8, 6 + 0*8),
+      (1 + 6*8, 1 + 1*8),
+      (6 + 0*8, 7 + 0*8),
+      (0 + 3*8, 0 + 0*8),
+    ];
+    for &(from, to) in &moves {
+      let move_ = game.legal_moves().into_iter()
+        .find(|m| m.from == Loc(from) && m.to == Loc(to) && m.component.is_none())
+        .unwrap();
+      game.play(move_);
+    }
+
+    assert!(game.result() == Some(GameResult::Checkmate(true)));
+    assert!(game.legal_moves().is_empty());
+    assert!(game.moves()[0].kind == MoveKind::Capture);
+    assert_eq!(game.moves().len(), 5);
+    assert_eq!(game.captured(false).count(), 1);
+    assert_eq!(game.captured(true).count(), 0);
+  }
+
+  #[test]
+  pub fn threefold_repetition() {
+    let mut game = Game::new(RuleSet::default());
+
+    let shuffle = [
+      (6 + 7*8, 5 + 5*8),
+      (6 + 0*8, 5 + 2*8),
+      (5 + 5*8, 6 + 7*8),
+      (5 + 2*8, 6 + 0*8),
+    ];
+    for _ in 0..2 {
+      assert!(game.result().is_none());
+
+      for &(from, to) in &shuffle {
+        let move_ = game.legal_moves().into_iter()
+          .find(|m| m.from == Loc(from) && m.to == Loc(to))
+          .unwrap();
+        game.play(move_);
+      }
+    }
+
+    assert!(game.result() == Some(GameResult::Draw(Draw::ThreefoldRepetition)));
+    assert_eq!(game.halfmove_clock(), 8);
+  }
+
+  #[test]
+  pub fn fifty_move_rule() {
+    let mut game = Game::new(RuleSet::default());
+    game.halfmove_clock = 98;
+
+    let knight = game.legal_moves().into_iter().find(|m| m.from == Loc(6 + 7*8)).unwrap();
+    game.play(knight);
+    assert!(game.result().is_none());
+
+    let pawn = game.legal_moves().into_iter().find(|m| m.from == Loc(4 + 1*8)).unwrap();
+    game.play(pawn);
+    assert!(game.result().is_none());
+    assert_eq!(game.halfmove_clock(), 0);
+
+    game.halfmove_clock = 99;
+    let knight = game.legal_moves().into_iter().find(|m| m.from == Loc(1 + 7*8)).unwrap();
+    game.play(knight);
+    assert!(game.result() == Some(GameResult::Draw(Draw::FiftyMoveRule)));
+  }
+}
@@ -0,0 +1,131 @@
+use super::Loc;
+
+// Files are lettered, and there has to be room for a back rank and a row of
+// pawns on each side.
+const MIN_SIZE: usize = 4;
+pub(crate) const MAX_SIZE: usize = 16;
+
+// How big a board is. Squares are numbered row by row starting from the top
+// left, which is black's side, so the square a `Loc` stands for depends on
+// the geometry of the board it belongs to.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+  width: i32,
+  height: i32,
+}
+
+impl Geometry {
+  pub const STANDARD: Geometry = Geometry{ width: 8, height: 8 };
+  // Capablanca chess's board
+  pub const WIDE: Geometry = Geometry{ width: 10, height: 8 };
+  pub const MINI: Geometry = Geometry{ width: 6, height: 6 };
+
+  pub fn new(width: usize, height: usize) -> Option<Self> {
+    if !(MIN_SIZE..=MAX_SIZE).contains(&width) || !(MIN_SIZE..=MAX_SIZE).contains(&height) { return None; }
+
+    Some(Geometry{ width: width as i32, height: height as i32 })
+  }
+
+  pub fn width(self) -> usize { self.width as usize }
+  pub fn height(self) -> usize { self.height as usize }
+  pub fn size(self) -> usize { (self.width * self.height) as usize }
+
+  pub fn squares(self) -> impl Iterator<Item=Loc> {
+    (0..self.width * self.height).map(Loc)
+  }
+
+  pub fn loc(self, x: usize, y: usize) -> Loc {
+    Loc(y as i32 * self.width + x as i32)
+  }
+
+  pub fn x(self, loc: Loc) -> usize { (loc.0 % self.width) as usize }
+  pub fn y(self, loc: Loc) -> usize { (loc.0 / self.width) as usize }
+
+  pub fn d(self, loc: Loc, dx: i32, dy: i32) -> Option<Loc> {
+    let nx = self.x(loc) as i32 + dx;
+    let ny = self.y(loc) as i32 + dy;
+
+    if nx < 0 || nx >= self.width || ny < 0 || ny >= self.height {
+      None
+    } else {
+      Some(Loc(ny * self.width + nx))
+    }
+  }
+
+  // The row each side's pieces start on, and the one its pawns start on
+  pub fn back_row(self, white: bool) -> usize { if white { self.height() - 1 } else { 0 } }
+  pub fn pawn_row(self, white: bool) -> usize { if white { self.height() - 2 } else { 1 } }
+
+  // From a square name like "e4"
+  pub fn parse(self, name: &str) -> Option<Loc> {
+    match self.parse_prefix(name) {
+      Some((loc, "")) => Some(loc),
+      _ => None,
+    }
+  }
+
+  // The square name at the start of `text`, and whatever follows it. Ranks
+  // past the ninth take two digits.
+  pub fn parse_prefix(self, text: &str) -> Option<(Loc, &str)> {
+    let file = text.chars().next().filter(|c| c.is_ascii_lowercase())?;
+    let rest = &text[1..];
+    let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    if rest.starts_with('0') { return None; }
+    let rank = rest[..digits].parse::<usize>().ok()?;
+
+    let x = (file as u8 - b'a') as usize;
+    if x >= self.width() || rank < 1 || rank > self.height() { return None; }
+
+    Some((self.loc(x, self.height() - rank), &rest[digits..]))
+  }
+
+  pub fn name(self, loc: Loc) -> String {
+    format!("{}{}", (b'a' + self.x(loc) as u8) as char, self.height() - self.y(loc))
+  }
+}
+
+// A square on the standard board, for tests
+#[cfg(test)]
+pub(crate) fn loc(name: &str) -> Loc {
+  Geometry::STANDARD.parse(name).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+  use super::Geometry;
+  use super::super::Loc;
+
+  #[test]
+  pub fn names() {
+    let standard = Geometry::STANDARD;
+    assert!(standard.parse("a8") == Some(Loc(0)));
+    assert!(standard.parse("e1") == Some(Loc(4 + 7*8)));
+    assert_eq!(standard.name(Loc(4 + 7*8)), "e1");
+    assert!(standard.parse("i1").is_none());
+    assert!(standard.parse("a9").is_none());
+    assert!(standard.parse("a0").is_none());
+    assert!(standard.parse("a01").is_none());
+    assert!(standard.parse("e4x").is_none());
+
+    let wide = Geometry::WIDE;
+    assert!(wide.parse("j1") == Some(Loc(9 + 7*10)));
+    assert_eq!(wide.name(Loc(9 + 7*10)), "j1");
+
+    let tall = Geometry::new(8, 12).unwrap();
+    assert!(tall.parse_prefix("a12a11") == Some((Loc(0), "a11")));
+    assert_eq!(tall.name(Loc(8)), "a11");
+
+    assert!(Geometry::new(3, 8).is_none());
+    assert!(Geometry::new(8, 17).is_none());
+  }
+
+  #[test]
+  pub fn steps() {
+    let mini = Geometry::MINI;
+    let corner = mini.parse("f1").unwrap();
+    assert!(mini.d(corner, 1, 0).is_none());
+    assert!(mini.d(corner, 0, 1).is_none());
+    assert!(mini.d(corner, -1, -1) == mini.parse("e2"));
+    assert_eq!(mini.squares().count(), 36);
+  }
+}
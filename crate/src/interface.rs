@@ -1,57 +1,104 @@
+use rand::Rng;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
-use super::{Board,Loc};
+use super::{Board,Draw,Game,GameResult,Geometry,Loc,Move,MoveKind,PgnError,Piece,RuleSet,WHITE,BLACK,QUEEN,ROOK,BISHOP,KNIGHT,CAMEL,ZEBRA,NIGHTRIDER};
 use super::ai;
+use super::registry::{piece_type,REGISTRY};
+use super::fen::parse_back_rank;
+use super::setup::CHESS960_POSITIONS;
 
 static mut THE_INTERFACE: Option<Interface> = None;
 
 #[derive(Clone)]
 enum State {
+  // Editing `Interface::setup_board`
   Setup,
   Playing,
   Selected {
     selected_loc: Loc,
-    available_moves: Vec<Loc>,
+    component: Option<u16>,
+    available_moves: Vec<Move>,
     check_moves: Vec<Loc>,
   },
-  Checkmate(bool),
-  Stalemate(bool),
+  Promoting(Move),
+  // For a castle that ends where the king could also just move to, or where
+  // it already stands
+  ChoosingCastle {
+    castle: Move,
+    other: Option<Move>,
+  },
+  GameOver(GameResult),
   AiMove,
 }
 
 pub struct Interface {
   state: State,
-  board: Board,
-  white_turn: bool,
-  prev_move: Option<(Loc, Loc)>,
+  game: Game,
   white_ai: bool,
   black_ai: bool,
+  setup_board: Board,
+  // The components clicking a square in the editor puts there. With none,
+  // clicking clears the square.
+  brush: u16,
 }
 
 impl Interface {
   pub fn setup() -> Self {
     Interface{
       state: State::Setup,
-      board: Board::empty(),
-      white_turn: true,
-      prev_move: None,
+      game: Game::new(RuleSet::default()),
       white_ai: false,
       black_ai: false,
+      setup_board: Board::fresh(),
+      brush: 0,
     }
   }
 
-  pub fn new(white_ai: bool, black_ai: bool) -> Self {
+  // Positions from the editor can already be over, as with bare kings or a
+  // side that's been mated
+  pub fn new(white_ai: bool, black_ai: bool, board: Board) -> Self {
+    let white_turn = board.white_turn();
+    let game = Game::from_board(board.clone(), white_turn);
+
     Interface{
-      state: if white_ai { State::AiMove } else { State::Playing },
-      board: Board::fresh(),
-      white_turn: true,
-      prev_move: None,
+      state: match game.result() {
+        Some(result) => State::GameOver(result),
+        None if (white_turn && white_ai) || (!white_turn && black_ai) => State::AiMove,
+        None => State::Playing,
+      },
+      game,
       white_ai,
       black_ai,
+      setup_board: board,
+      brush: 0,
     }
   }
 
+  // Imported games carry on with humans playing both sides
+  pub fn from_pgn(pgn: &str) -> Result<Self, PgnError> {
+    let game = Game::from_pgn(pgn)?;
+
+    Ok(Interface{
+      state: State::Playing,
+      setup_board: game.start().clone(),
+      game,
+      white_ai: false,
+      black_ai: false,
+      brush: 0,
+    })
+  }
+
+  // The editor's board while setting up, and otherwise the game's
+  fn shown_board(&self) -> &Board {
+    if let State::Setup = self.state { &self.setup_board } else { self.game.board() }
+  }
+
+  pub fn to_pgn(&self) -> String {
+    let player = |ai| if ai { "AI" } else { "Human" };
+    self.game.to_pgn(&[("White", player(self.white_ai)), ("Black", player(self.black_ai))])
+  }
+
   pub fn render(&self) {
     let window = web_sys::window().expect("window");
     let document = window.document().expect("document");
@@ -59,29 +106,31 @@ impl Interface {
     let message = document.get_element_by_id("message").expect("#message");
     message.set_text_content(None);
 
+    let geometry = self.shown_board().geometry();
     let table = document.get_element_by_id("chess-board").expect("#chess-board").first_element_child().expect("tbody");
+    resize_board(&document, &table, geometry);
 
-    for y in 0..8 {
-      let tr = table.children().get_with_index(y).expect("tr");
+    for y in 0..geometry.height() {
+      let tr = table.children().get_with_index(y as u32).expect("tr");
 
-      for x in 0..8 {
-        let td = tr.children().get_with_index(x).expect("td");
+      for x in 0..geometry.width() {
+        let td = tr.children().get_with_index(x as u32).expect("td");
 
-        let loc = Loc((y * 8 + x) as i32);
+        let loc = geometry.loc(x, y);
 
         let cell_color = if ((x + y) % 2) == 0 { "white" } else { "black" };
         let mut target_classes = cell_color.to_owned();
 
-        if let Some((from, to)) = self.prev_move {
-          if from == loc || to == loc {
+        if let Some(prev_move) = self.game.last_move().filter(|_| !matches!(self.state, State::Setup)) {
+          if prev_move.from == loc || prev_move.to == loc {
             target_classes += " prev-move";
           }
         }
 
-        if let State::Selected{selected_loc, ref available_moves, ref check_moves} = self.state {
+        if let State::Selected{selected_loc, ref available_moves, ref check_moves, ..} = self.state {
           if loc == selected_loc {
             target_classes += " selected";
-          } else if available_moves.contains(&loc) {
+          } else if available_moves.iter().any(|m| m.to == loc) {
             target_classes += " available-move";
           } else if check_moves.contains(&loc) {
             target_classes += " check-move";
@@ -90,23 +139,24 @@ impl Interface {
 
         let td = td.dyn_into::<web_sys::HtmlElement>().unwrap();
 
-        let piece = self.board.piece(loc);
+        let board = self.shown_board();
+        let piece = board.piece(loc);
+        let mut fairy_letters = String::new();
 
         if !piece.is_empty() {
           let piece_color = if piece.is_white() { "white" } else { "black" };
           target_classes += " piece-";
           target_classes += piece_color;
 
-          if piece.is_king()   { target_classes += " king";   }
-          if piece.is_queen()  { target_classes += " queen";  }
-          if piece.is_rook()   { target_classes += " rook";   }
-          if piece.is_bishop() { target_classes += " bishop"; }
-          if piece.is_knight() { target_classes += " knight"; }
-          if piece.is_pawn()   { target_classes += " pawn";   }
+          for component in piece.components() {
+            target_classes += " ";
+            target_classes += piece_type(component).name;
+            if component & (CAMEL | ZEBRA | NIGHTRIDER) != 0 { fairy_letters.push(piece_type(component).letter); }
+          }
 
           if piece.is_hybrid() { target_classes += " hybrid"; }
 
-          if piece.is_king() && self.board.is_check(piece.is_white()) {
+          if piece.is_king() && board.is_check(piece.is_white()) {
             message.set_text_content(Some("Check!"));
             target_classes += " check";
           }
@@ -115,24 +165,57 @@ impl Interface {
         if td.class_name() != target_classes {
           td.set_class_name(&target_classes);
         }
+
+        // There are no images for fairy pieces, so their letters stand in
+        if fairy_letters.is_empty() {
+          td.remove_attribute("data-fairy").unwrap();
+        } else if td.get_attribute("data-fairy").as_ref() != Some(&fairy_letters) {
+          td.set_attribute("data-fairy", &fairy_letters).unwrap();
+        }
       }
     }
 
-    if let State::Checkmate(white) = self.state {
+    if let State::GameOver(GameResult::Checkmate(white)) = self.state {
       let c = if white { "White" } else { "Black" };
       let m = format!("Checkmate! {} wins", c);
       message.set_text_content(Some(&m));
-    } else if let State::Stalemate(_) = self.state {
-      message.set_text_content(Some("Stalemate!"));
+    } else if let State::GameOver(GameResult::Draw(draw)) = self.state {
+      let m = match draw {
+        Draw::Stalemate => "Stalemate!",
+        _ => draw.description(),
+      };
+      message.set_text_content(Some(m));
     } else if let State::AiMove = self.state {
       message.set_text_content(Some("AI is thinking..."));
+    } else if let State::Promoting(_) = self.state {
+      message.set_text_content(Some("Promote to..."));
+    } else if let State::ChoosingCastle{..} = self.state {
+      message.set_text_content(Some("Castle?"));
+    } else if let State::Selected{component: Some(component), ..} = self.state {
+      let m = format!("Moving only the {}", piece_type(component).name);
+      message.set_text_content(Some(&m));
+    }
+
+    {
+      let promotion = document.get_element_by_id("promotion").expect("#promotion");
+      let promotion_class = if let State::Promoting(_) = self.state { "" } else { "hidden" };
+      if promotion.class_name() != promotion_class {
+        promotion.set_class_name(promotion_class);
+      }
+    }
+
+    {
+      let castle_choice = document.get_element_by_id("castle-choice").expect("#castle-choice");
+      let castle_choice_class = if let State::ChoosingCastle{..} = self.state { "" } else { "hidden" };
+      if castle_choice.class_name() != castle_choice_class {
+        castle_choice.set_class_name(castle_choice_class);
+      }
     }
 
     {
       let show_start_buttons = match self.state {
         State::Setup
-          | State::Checkmate(_)
-          | State::Stalemate(_)
+          | State::GameOver(_)
           => true,
         _ => false,
       };
@@ -142,6 +225,17 @@ impl Interface {
         setup.set_class_name(&setup_class);
       }
     }
+
+    {
+      let palette = document.get_element_by_id("palette").expect("#palette").children();
+      for (i, piece_type) in REGISTRY.iter().enumerate() {
+        let button = palette.get_with_index(i as u32).expect("palette button");
+        let button_class = if self.brush & piece_type.component != 0 { "selected" } else { "" };
+        if button.class_name() != button_class {
+          button.set_class_name(button_class);
+        }
+      }
+    }
   }
 
   fn set_state(&mut self, new_state: State) {
@@ -152,20 +246,23 @@ impl Interface {
   pub fn do_ai_move(&mut self) {
     assert!(matches!(self.state, State::AiMove));
 
-    let ai_move = ai::choose_minimax(&self.board, self.white_turn);
-    self.board = self.board.move_(ai_move.0, ai_move.1);
-    self.prev_move = Some(ai_move);
+    let ai_move = ai::choose_minimax(&self.game);
+    self.play(ai_move);
+  }
+
+  fn play(&mut self, move_: Move) {
+    self.game.play(move_);
 
     self.post_move();
   }
 
   fn post_move(&mut self) {
-    if let Some(mate_state) = self.mate_state() {
-      self.set_state(mate_state);
+    if let Some(result) = self.game.result() {
+      self.set_state(State::GameOver(result));
     } else {
-      self.white_turn = !self.white_turn;
+      let white_turn = self.game.white_turn();
 
-      if (self.white_turn && self.white_ai) || (!self.white_turn && self.black_ai) {
+      if (white_turn && self.white_ai) || (!white_turn && self.black_ai) {
         self.set_state(State::AiMove);
         Self::schedule_ai_move();
       } else {
@@ -174,39 +271,84 @@ impl Interface {
     }
   }
 
-  pub fn clicked(&mut self, x: i32, y: i32) {
-    let loc = Loc(y * 8 + x);
+  pub fn clicked(&mut self, x: usize, y: usize) {
+    let geometry = self.shown_board().geometry();
+    if x >= geometry.width() || y >= geometry.height() { return; }
+    let loc = geometry.loc(x, y);
 
     match self.state.clone() {
       State::Playing => {
-        let piece = self.board.piece(loc);
-
-        if piece.is_white() == self.white_turn {
-          let available_moves = self.board.moves_from(loc);
+        let piece = self.game.board().piece(loc);
 
-          if let Some(available_moves) = available_moves {
-            let (check_moves, available_moves) = available_moves.into_iter().partition(|&to| self.board.move_(loc, to).is_check(piece.is_white()));
-            self.set_state(State::Selected{selected_loc: loc, available_moves, check_moves});
-          }
+        if !piece.is_empty() && piece.is_white() == self.game.white_turn() {
+          self.select(loc, None);
         }
       },
-      State::Selected{selected_loc, ref available_moves, ..} => {
-        if available_moves.contains(&loc) {
-          self.board = self.board.move_(selected_loc, loc);
-          self.prev_move = Some((selected_loc, loc));
-
-          self.post_move();
+      State::Selected{selected_loc, component, ref available_moves, ..} => {
+        let castle = available_moves.iter().cloned().find(|m| m.to == loc && m.kind == MoveKind::Castle);
+        let other = available_moves.iter().cloned().find(|m| m.to == loc && m.kind != MoveKind::Castle);
+
+        if let Some(castle) = castle.filter(|_| other.is_some() || loc == selected_loc) {
+          self.set_state(State::ChoosingCastle{castle, other});
+        } else if let Some(move_) = other.or(castle) {
+          if let MoveKind::Promotion(_) = move_.kind {
+            self.set_state(State::Promoting(move_));
+          } else {
+            self.play(move_);
+          }
         } else if selected_loc == loc {
-          self.set_state(State::Playing);
+          // Clicking a selected hybrid again cycles through moving each of its
+          // components on their own.
+          let piece = self.game.board().piece(loc);
+          let next_component = if !piece.is_hybrid() {
+            None
+          } else if let Some(component) = component {
+            piece.components().skip_while(|&c| c != component).nth(1)
+          } else {
+            piece.components().next()
+          };
+
+          if next_component.is_some() {
+            self.select(loc, next_component);
+          } else {
+            self.set_state(State::Playing);
+          }
         }
       },
-      State::Checkmate(_) => {},
-      State::Stalemate(_) => {},
+      State::Promoting(_) => {},
+      State::ChoosingCastle{..} => {},
+      State::GameOver(_) => {},
       State::AiMove => {},
-      State::Setup => {},
+      State::Setup => self.edit(loc),
     };
   }
 
+  // Puts the brush's piece on the square, in the color picked for it, or
+  // takes off what's there if it's the same piece
+  fn edit(&mut self, loc: Loc) {
+    let window = web_sys::window().expect("window");
+    let document = window.document().expect("document");
+    let white = document.get_element_by_id("editor-color").expect("#editor-color")
+      .dyn_into::<web_sys::HtmlSelectElement>().unwrap()
+      .value() == "white";
+
+    let piece = if self.brush == 0 { Piece::EMPTY } else { Piece(if white { WHITE } else { BLACK } | self.brush) };
+    let piece = if self.setup_board.piece(loc) == piece { Piece::EMPTY } else { piece };
+    self.setup_board.place(loc, piece);
+    self.render();
+  }
+
+  pub fn toggle_brush(&mut self, component: u16) {
+    self.brush ^= component;
+    self.render();
+  }
+
+  // Also leaves a finished game for the editor
+  pub fn edit_board(&mut self, board: Board) {
+    self.setup_board = board;
+    self.set_state(State::Setup);
+  }
+
   fn schedule_ai_move() {
     static mut CALLBACK: Option<wasm_bindgen::prelude::Closure<dyn std::ops::Fn()>> = None;
 
@@ -218,24 +360,75 @@ impl Interface {
     window.set_timeout_with_callback(callback.as_ref().unchecked_ref()).unwrap();
   }
 
-  fn mate_state(&self) -> Option<State> {
-    if self.board.is_check_mate(!self.white_turn) {
-      Some(State::Checkmate(self.white_turn))
-    } else if self.board.is_stale_mate(!self.white_turn) {
-      Some(State::Stalemate(self.white_turn))
-    } else {
-      None
+  fn select(&mut self, loc: Loc, component: Option<u16>) {
+    let board = self.game.board();
+    let dests = match component {
+      Some(component) => board.split_moves_from(loc, component),
+      None => board.moves_from(loc),
+    };
+
+    if let Some(dests) = dests {
+      let available_moves = self.game.legal_moves().into_iter()
+        .filter(|m| m.from == loc && m.component == component)
+        .collect::<Vec<_>>();
+      let check_moves = dests.into_iter()
+        .filter(|&to| !available_moves.iter().any(|m| m.to == to))
+        .collect();
+      self.set_state(State::Selected{selected_loc: loc, component, available_moves, check_moves});
+    }
+  }
+
+  pub fn promote(&mut self, promotion: u16) {
+    if let State::Promoting(move_) = self.state {
+      self.play(Move{kind: MoveKind::Promotion(promotion), ..move_});
+    }
+  }
+
+  // Declining a castle that leaves the king where it is just deselects it
+  pub fn choose_castle(&mut self, castle: bool) {
+    if let State::ChoosingCastle{castle: castle_move, other} = self.state {
+      match if castle { Some(castle_move) } else { other } {
+        Some(move_) => self.play(move_),
+        None => self.set_state(State::Playing),
+      }
     }
   }
 
   pub fn clicked_out(&mut self) {
-    if matches!(self.state, State::Selected{..}) {
+    if matches!(self.state, State::Selected{..} | State::Promoting(_) | State::ChoosingCastle{..}) {
       self.state = State::Playing;
       self.render();
     }
   }
 }
 
+// Rebuilds the cells of `#chess-board` when the board changes size, sizing
+// them to keep the board square. Each cell knows its coordinates for `init`'s
+// click handler.
+fn resize_board(document: &web_sys::Document, tbody: &web_sys::Element, geometry: Geometry) {
+  let rows = tbody.children();
+  let width = rows.get_with_index(0).map_or(0, |tr| tr.children().length());
+  if rows.length() == geometry.height() as u32 && width == geometry.width() as u32 { return; }
+
+  tbody.set_inner_html("");
+  let percent = format!("{}%", 100.0 / geometry.width() as f64);
+
+  for y in 0..geometry.height() {
+    let tr = document.create_element("tr").unwrap();
+
+    for x in 0..geometry.width() {
+      let td = document.create_element("td").unwrap().dyn_into::<web_sys::HtmlElement>().unwrap();
+      td.set_attribute("data-x", &x.to_string()).unwrap();
+      td.set_attribute("data-y", &y.to_string()).unwrap();
+      td.style().set_property("width", &percent).unwrap();
+      td.style().set_property("padding-bottom", &percent).unwrap();
+      tr.append_child(&td).unwrap();
+    }
+
+    tbody.append_child(&tr).unwrap();
+  }
+}
+
 pub fn the_interface() -> &'static mut Interface {
   unsafe{ THE_INTERFACE.as_mut().unwrap() }
 }
@@ -256,30 +449,91 @@ pub fn init() {
   }
 
   {
-    let table = document.get_element_by_id("chess-board").expect("#chess-board").first_element_child().expect("tbody");
-
-    for y in 0..8 {
-      let tr = table.children().get_with_index(y).expect("tr");
-
-      for x in 0..8 {
-        let td = tr.children().get_with_index(x).expect("td");
-
-        let clicked_callback = Closure::wrap(Box::new(move |event: web_sys::Event|{
-          the_interface().clicked(x as i32, y as i32);
+    // One handler for the whole board, since its cells come and go with its
+    // size
+    let table = document.get_element_by_id("chess-board").expect("#chess-board");
+
+    let clicked_callback = Closure::wrap(Box::new(move |event: web_sys::Event|{
+      let td = event.target()
+        .and_then(|target| target.dyn_into::<web_sys::Element>().ok())
+        .and_then(|element| element.closest("td").unwrap());
+      let coordinate = |td: &web_sys::Element, name| td.get_attribute(name).and_then(|value| value.parse().ok());
+
+      if let Some(td) = td {
+        if let (Some(x), Some(y)) = (coordinate(&td, "data-x"), coordinate(&td, "data-y")) {
+          the_interface().clicked(x, y);
           event.stop_propagation();
-        }) as Box<Fn(web_sys::Event)>);
-        td.add_event_listener_with_callback("click", clicked_callback.as_ref().unchecked_ref()).unwrap();
-        clicked_callback.forget();
+        }
       }
+    }) as Box<Fn(web_sys::Event)>);
+    table.add_event_listener_with_callback("click", clicked_callback.as_ref().unchecked_ref()).unwrap();
+    clicked_callback.forget();
+  }
+
+  // The position picked on the setup screen, or why it can't be played
+  fn starting_board(document: &web_sys::Document, rules: RuleSet) -> Result<Board, String> {
+    let select = |id: &str| document.get_element_by_id(id).expect(id).dyn_into::<web_sys::HtmlSelectElement>().unwrap();
+    let input = |id: &str| document.get_element_by_id(id).expect(id).dyn_into::<web_sys::HtmlInputElement>().unwrap();
+
+    match select("start-position").value().as_str() {
+      "chess960" => {
+        let number_input = input("chess960-number");
+        let number = match number_input.value().trim() {
+          "" => rand::thread_rng().gen_range(0, CHESS960_POSITIONS),
+          number => number.parse().ok().filter(|&n| n < CHESS960_POSITIONS)
+            .ok_or_else(|| format!("Chess960 positions are numbered 0 to {}", CHESS960_POSITIONS - 1))?,
+        };
+        // Left showing so the same position can be played again
+        number_input.set_value(&number.to_string());
+
+        Ok(Board::chess960(number, rules))
+      },
+      "wide" => Ok(Board::starting(Geometry::WIDE, rules).expect("The wide board has a starting position")),
+      "mini" => Ok(Board::starting(Geometry::MINI, rules).expect("The mini board has a starting position")),
+      // Whatever's been set up in the editor, as long as it could come up in a
+      // game
+      "editor" => {
+        let mut board = the_interface().setup_board.clone();
+        board.rules = rules;
+        board.set_white_turn(select("side-to-move").value() == "white");
+
+        let problems = board.validate().iter().map(|problem| problem.to_string()).collect::<Vec<_>>();
+        if !problems.is_empty() { return Err(format!("Can't start from here: {}", problems.join(", "))); }
+        Ok(board)
+      },
+      "custom" => {
+        let back_rank = parse_back_rank(&input("back-rank").value()).map_err(|error| format!("Bad back rank: {}", error))?;
+        // As wide as the back rank, with the usual eight ranks
+        let geometry = Geometry::new(back_rank.len(), 8).ok_or_else(|| "Back ranks can be 4 to 16 squares wide".to_owned())?;
+        Board::with_back_rank(geometry, &back_rank, rules).ok_or_else(|| "The back rank needs exactly one king".to_owned())
+      },
+      _ => Ok(Board::with_rules(rules)),
     }
   }
 
   fn start_new_game(white_ai: bool, black_ai: bool) {
-    unsafe{ THE_INTERFACE = Some(Interface::new(white_ai, black_ai)); }
-    if white_ai { Interface::schedule_ai_move(); }
+    let window = web_sys::window().expect("window");
+    let document = window.document().expect("document");
+    let board = match starting_board(&document, rules(&document)) {
+      Ok(board) => board,
+      Err(error) => {
+        document.get_element_by_id("message").expect("#message").set_text_content(Some(&error));
+        return;
+      },
+    };
+
+    unsafe{ THE_INTERFACE = Some(Interface::new(white_ai, black_ai, board)); }
+    if let State::AiMove = the_interface().state { Interface::schedule_ai_move(); }
     the_interface().render();
   }
 
+  fn rules(document: &web_sys::Document) -> RuleSet {
+    let preset = document.get_element_by_id("rules").expect("#rules")
+      .dyn_into::<web_sys::HtmlSelectElement>().unwrap()
+      .value();
+    RuleSet::preset(&preset).unwrap_or_default()
+  }
+
   let modes = [
     ("play-as-white", false, true),
     ("play-as-black", true, false),
@@ -294,5 +548,128 @@ pub fn init() {
     callback.forget();
   }
 
+  let promotions = [
+    ("promote-queen", QUEEN),
+    ("promote-rook", ROOK),
+    ("promote-bishop", BISHOP),
+    ("promote-knight", KNIGHT),
+  ];
+
+  for &(button_id, promotion) in &promotions {
+    let button = document.get_element_by_id(button_id).expect(button_id);
+    let callback = Closure::wrap(Box::new(move || the_interface().promote(promotion)) as Box<Fn()>);
+    button.add_event_listener_with_callback("click", callback.as_ref().unchecked_ref()).unwrap();
+    callback.forget();
+  }
+
+  {
+    let palette = document.get_element_by_id("palette").expect("#palette");
+
+    for piece_type in REGISTRY.iter() {
+      let button = document.create_element("button").unwrap();
+      button.set_text_content(Some(piece_type.name));
+      palette.append_child(&button).unwrap();
+
+      let component = piece_type.component;
+      let callback = Closure::wrap(Box::new(move || the_interface().toggle_brush(component)) as Box<Fn()>);
+      button.add_event_listener_with_callback("click", callback.as_ref().unchecked_ref()).unwrap();
+      callback.forget();
+    }
+  }
+
+  {
+    // Puts the picked starting position in the editor to change from there
+    let button = document.get_element_by_id("load-start").expect("load-start");
+    let callback = Closure::wrap(Box::new(move || {
+      let window = web_sys::window().expect("window");
+      let document = window.document().expect("document");
+      if let State::AiMove = the_interface().state { return; }
+
+      match starting_board(&document, rules(&document)) {
+        Ok(board) => the_interface().edit_board(board),
+        Err(error) => document.get_element_by_id("message").expect("#message").set_text_content(Some(&error)),
+      }
+    }) as Box<Fn()>);
+    button.add_event_listener_with_callback("click", callback.as_ref().unchecked_ref()).unwrap();
+    callback.forget();
+  }
+
+  {
+    let button = document.get_element_by_id("clear-board").expect("clear-board");
+    let callback = Closure::wrap(Box::new(move || {
+      if let State::AiMove = the_interface().state { return; }
+
+      let geometry = the_interface().shown_board().geometry();
+      the_interface().edit_board(Board::empty_with_geometry(geometry));
+    }) as Box<Fn()>);
+    button.add_event_listener_with_callback("click", callback.as_ref().unchecked_ref()).unwrap();
+    callback.forget();
+  }
+
+  for &(button_id, castle) in &[("castle", true), ("dont-castle", false)] {
+    let button = document.get_element_by_id(button_id).expect(button_id);
+    let callback = Closure::wrap(Box::new(move || the_interface().choose_castle(castle)) as Box<Fn()>);
+    button.add_event_listener_with_callback("click", callback.as_ref().unchecked_ref()).unwrap();
+    callback.forget();
+  }
+
+  fn pgn_textarea() -> web_sys::HtmlTextAreaElement {
+    let window = web_sys::window().expect("window");
+    let document = window.document().expect("document");
+    document.get_element_by_id("pgn").expect("#pgn")
+      .dyn_into::<web_sys::HtmlTextAreaElement>().unwrap()
+  }
+
+  {
+    let button = document.get_element_by_id("export-pgn").expect("export-pgn");
+    let callback = Closure::wrap(Box::new(move || pgn_textarea().set_value(&the_interface().to_pgn())) as Box<Fn()>);
+    button.add_event_listener_with_callback("click", callback.as_ref().unchecked_ref()).unwrap();
+    callback.forget();
+  }
+
+  {
+    let button = document.get_element_by_id("import-pgn").expect("import-pgn");
+    let callback = Closure::wrap(Box::new(move || {
+      // The AI's move is already scheduled and would land in the new game
+      if let State::AiMove = the_interface().state { return; }
+
+      match Interface::from_pgn(&pgn_textarea().value()) {
+        Ok(interface) => {
+          unsafe{ THE_INTERFACE = Some(interface); }
+          the_interface().post_move();
+        },
+        Err(error) => {
+          let window = web_sys::window().expect("window");
+          let document = window.document().expect("document");
+          let message = document.get_element_by_id("message").expect("#message");
+          message.set_text_content(Some(&format!("Couldn't import PGN: {}", error)));
+        },
+      }
+    }) as Box<Fn()>);
+    button.add_event_listener_with_callback("click", callback.as_ref().unchecked_ref()).unwrap();
+    callback.forget();
+  }
+
   the_interface().render();
 }
+
+#[cfg(test)]
+mod test {
+  use super::{Interface,State};
+  use super::super::{Board,Draw,GameResult};
+
+  #[test]
+  pub fn finished_positions() {
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - -").unwrap();
+    assert!(matches!(Interface::new(true, true, board).state, State::GameOver(GameResult::Draw(Draw::InsufficientMaterial))));
+
+    let board = Board::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - -").unwrap();
+    assert!(matches!(Interface::new(false, true, board).state, State::GameOver(GameResult::Draw(Draw::Stalemate))));
+
+    let board = Board::from_fen("k6R/8/1K6/8/8/8/8/8 b - -").unwrap();
+    assert!(matches!(Interface::new(false, false, board).state, State::GameOver(GameResult::Checkmate(true))));
+
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 b - -").unwrap();
+    assert!(matches!(Interface::new(false, true, board).state, State::AiMove));
+  }
+}
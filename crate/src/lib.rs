@@ -1,24 +1,56 @@
 extern crate cfg_if;
+#[cfg(feature = "web")]
 extern crate wasm_bindgen;
+#[cfg(feature = "web")]
 extern crate web_sys;
-#[macro_use]
+#[cfg_attr(any(test, feature = "web"), macro_use)]
 extern crate matches;
 extern crate rand;
-
-mod ai;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
+pub mod ai;
+mod bitboard;
+mod fen;
+mod game;
+mod geometry;
+mod notation;
+mod perft;
+mod pgn;
+#[cfg(feature = "web")]
 pub mod interface;
-
+mod registry;
+mod rules;
+#[cfg(feature = "serde")]
+mod serialize;
+mod setup;
+mod validate;
+mod zobrist;
+
+pub use bitboard::Bitboards;
+pub use fen::{parse_back_rank,FenError};
+pub use game::{Draw,Game,GameResult};
+pub use geometry::Geometry;
+pub use notation::NotationError;
+pub use pgn::PgnError;
+#[cfg(feature = "web")]
 pub use interface::Interface;
+pub use rules::RuleSet;
+pub use validate::{MoveError,Problem};
 
 use cfg_if::cfg_if;
 
+use registry::{Movement,REGISTRY};
+
 cfg_if! {
     // When the `console_error_panic_hook` feature is enabled, we can call the
     // `set_panic_hook` function to get better error messages if we ever panic.
-    if #[cfg(feature = "console_error_panic_hook")] {
+    if #[cfg(all(feature = "web", feature = "console_error_panic_hook"))] {
         extern crate console_error_panic_hook;
         use console_error_panic_hook::set_once as set_panic_hook;
-    } else {
+    } else if #[cfg(feature = "web")] {
         #[inline]
         fn set_panic_hook() {}
     }
@@ -34,44 +66,96 @@ cfg_if! {
     }
 }
 
+// A square, numbered as its board's `Geometry` describes
 #[repr(transparent)]
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Loc(i32);
 
-impl Loc {
-  fn x(self) -> usize { (self.0 % 8) as usize }
-  fn y(self) -> usize { (self.0 / 8) as usize }
+const KING: u16       = 1;
+const QUEEN: u16      = 2;
+const ROOK: u16       = 4;
+const BISHOP: u16     = 8;
+const KNIGHT: u16     = 16;
+const PAWN: u16       = 32;
+// Fairy pieces, which `registry` describes
+const CAMEL: u16      = 64;
+const ZEBRA: u16      = 128;
+const NIGHTRIDER: u16 = 256;
+
+const PROMOTIONS: [u16; 4] = [QUEEN, ROOK, BISHOP, KNIGHT];
+
+fn component_letter(component: u16) -> char {
+  registry::piece_type(component).letter
+}
+
+// Either case
+fn letter_component(letter: char) -> Option<u16> {
+  REGISTRY.iter().find(|t| t.letter == letter.to_ascii_uppercase()).map(|t| t.component)
+}
 
-  fn d(self, dx: i32, dy: i32) -> Option<Self> {
-    let nx = self.x() as i32 + dx;
-    let ny = self.y() as i32 + dy;
+const WHITE_KINGSIDE: u8  = 1;
+const WHITE_QUEENSIDE: u8 = 2;
+const BLACK_KINGSIDE: u8  = 4;
+const BLACK_QUEENSIDE: u8 = 8;
 
-    if nx < 0 || nx > 7 || ny < 0 || ny > 7 {
-      None
-    } else {
-      Some(Loc(ny * 8 + nx))
-    }
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Castle {
+  right: u8,
+  white: bool,
+  king_from: Loc,
+  king_to: Loc,
+  rook_from: Loc,
+  rook_to: Loc,
+}
+
+impl Castle {
+  // Wherever the king and rook start out, they end up on the same squares as
+  // in ordinary chess, counting from the edge of the board.
+  fn new(right: u8, king_from: Loc, rook_from: Loc, geometry: Geometry) -> Self {
+    let white = right & (WHITE_KINGSIDE | WHITE_QUEENSIDE) != 0;
+    let width = geometry.width();
+    let (king_x, rook_x) = if right & (WHITE_KINGSIDE | BLACK_KINGSIDE) != 0 { (width - 2, width - 3) } else { (2, 3) };
+    let y = geometry.y(king_from);
+
+    Castle{ right, white, king_from, king_to: geometry.loc(king_x, y), rook_from, rook_to: geometry.loc(rook_x, y) }
   }
+
+  // With the king on the middle file and the rook in the corner
+  fn usual(right: u8, geometry: Geometry) -> Self {
+    let y = geometry.back_row(right & (WHITE_KINGSIDE | WHITE_QUEENSIDE) != 0);
+    let rook_x = if right & (WHITE_KINGSIDE | BLACK_KINGSIDE) != 0 { geometry.width() - 1 } else { 0 };
+
+    Castle::new(right, geometry.loc(geometry.width() / 2, y), geometry.loc(rook_x, y), geometry)
+  }
+
+  // Both ends included
+  fn king_path(self) -> impl Iterator<Item=Loc> { span(self.king_from, self.king_to) }
+  fn rook_path(self) -> impl Iterator<Item=Loc> { span(self.rook_from, self.rook_to) }
 }
 
-const KING: u8       = 1;
-const QUEEN: u8      = 2;
-const ROOK: u8       = 4;
-const BISHOP: u8     = 8;
-const KNIGHT: u8     = 16;
-const PAWN: u8       = 32;
+// Castling is only possible with an unmerged king and rook: moving either of
+// them, or merging anything into them, forfeits the right. Each board keeps
+// where its kings and rooks start, in this order.
+const CASTLING_RIGHTS: [u8; 4] = [WHITE_KINGSIDE, WHITE_QUEENSIDE, BLACK_KINGSIDE, BLACK_QUEENSIDE];
 
-const WHITE: u8      = 0x80;
-const BLACK: u8      = 0x00;
-const COLOR_MASK: u8 = 0x80;
+fn span(a: Loc, b: Loc) -> impl Iterator<Item=Loc> {
+  (a.0.min(b.0)..=a.0.max(b.0)).map(Loc)
+}
 
+
+const WHITE: u16      = 0x8000;
+const BLACK: u16      = 0x0000;
+const COLOR_MASK: u16 = 0x8000;
+
+// The color bit and one bit per component, from the registry
 #[repr(transparent)]
 #[derive(Clone, Copy, PartialEq, Eq)]
-struct Piece(u8);
+pub struct Piece(u16);
 
+// Starting positions are built from component bits, so these are mostly for
+// setting up boards by hand.
+#[allow(dead_code)]
 impl Piece {
-  const EMPTY: Piece = Piece(0);
-
   const WHITE_KING:   Piece = Piece(WHITE | KING);
   const WHITE_QUEEN:  Piece = Piece(WHITE | QUEEN);
   const WHITE_ROOK:   Piece = Piece(WHITE | ROOK);
@@ -85,12 +169,17 @@ impl Piece {
   const BLACK_BISHOP: Piece = Piece(BLACK | BISHOP);
   const BLACK_KNIGHT: Piece = Piece(BLACK | KNIGHT);
   const BLACK_PAWN:   Piece = Piece(BLACK | PAWN);
+}
+
+impl Piece {
+  const EMPTY: Piece = Piece(0);
 
   #[inline] pub fn empty() -> Self { Piece(0) }
 
   #[inline] pub fn is_empty(self) -> bool { self.0 == 0 }
 
   #[inline] pub fn is_white(self) -> bool { (self.0 & COLOR_MASK) == WHITE }
+  #[inline] pub fn color(self) -> u16 { self.0 & COLOR_MASK }
   // #[inline] pub fn is_black(self) -> bool { (self.0 & COLOR_MASK) == BLACK }
 
   #[inline] pub fn is_king(self)   -> bool { (self.0 & KING)   != 0 }
@@ -106,74 +195,367 @@ impl Piece {
   }
 
   #[inline] pub fn merge(self, other: Self) -> Self { Piece(self.0 | other.0) }
+
+  pub fn components(self) -> impl Iterator<Item=u16> {
+    REGISTRY.iter().map(|t| t.component).filter(move |&c| (self.0 & c) != 0)
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MoveKind {
+  Quiet,
+  // Includes en passant
+  Capture,
+  Merge,
+  Promotion(u16),
+  Castle,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+  pub from: Loc,
+  pub to: Loc,
+  // When set, only this one component of a hybrid moves and the rest of it
+  // stays behind on `from`.
+  pub component: Option<u16>,
+  pub kind: MoveKind,
+}
+
+// Everything `make_move` needs to put back
+pub struct Undo {
+  // The squares the move changed, with what was on them beforehand
+  squares: [(Loc, Piece); 4],
+  len: usize,
+  castling: u8,
+  en_passant: Option<Loc>,
+  white_turn: bool,
+  hash: u64,
 }
 
 // #[wasm_bindgen]
+#[derive(Clone, PartialEq, Eq)]
 pub struct Board {
-  pieces: [Piece; 64],
+  geometry: Geometry,
+  pieces: Vec<Piece>,
+  castling: u8,
+  en_passant: Option<Loc>,
+  white_turn: bool,
+  rules: RuleSet,
+  // Where each castling right's king and rook start from
+  castles: [Castle; 4],
+  // Zobrist hash of the pieces, castling rights, en passant square and side
+  // to move, kept up to date by `make_move`
+  hash: u64,
 }
 
 // #[wasm_bindgen]
 impl Board {
   pub fn fresh() -> Self {
-    Self{
-      pieces: [
-        Piece::BLACK_ROOK,
-        Piece::BLACK_KNIGHT,
-        Piece::BLACK_BISHOP,
-        Piece::BLACK_QUEEN,
-        Piece::BLACK_KING,
-        Piece::BLACK_BISHOP,
-        Piece::BLACK_KNIGHT,
-        Piece::BLACK_ROOK,
-        Piece::BLACK_PAWN, Piece::BLACK_PAWN, Piece::BLACK_PAWN, Piece::BLACK_PAWN, Piece::BLACK_PAWN, Piece::BLACK_PAWN, Piece::BLACK_PAWN, Piece::BLACK_PAWN,
-        Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY,
-        Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY,
-        Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY,
-        Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY, Piece::EMPTY,
-        Piece::WHITE_PAWN, Piece::WHITE_PAWN, Piece::WHITE_PAWN, Piece::WHITE_PAWN, Piece::WHITE_PAWN, Piece::WHITE_PAWN, Piece::WHITE_PAWN, Piece::WHITE_PAWN,
-        Piece::WHITE_ROOK,
-        Piece::WHITE_KNIGHT,
-        Piece::WHITE_BISHOP,
-        Piece::WHITE_QUEEN,
-        Piece::WHITE_KING,
-        Piece::WHITE_BISHOP,
-        Piece::WHITE_KNIGHT,
-        Piece::WHITE_ROOK,
-      ]
-    }
+    Self::with_rules(RuleSet::default())
+  }
+
+  pub fn with_rules(rules: RuleSet) -> Self {
+    Self::starting(Geometry::STANDARD, rules).expect("The standard board has a starting position")
   }
 
   pub fn empty() -> Self {
-    Self{ pieces: [Piece::EMPTY; 64] }
+    Self::empty_with_geometry(Geometry::STANDARD)
+  }
+
+  pub fn empty_with_geometry(geometry: Geometry) -> Self {
+    let mut board = Self{
+      geometry,
+      pieces: vec![Piece::EMPTY; geometry.size()],
+      castling: 0,
+      en_passant: None,
+      white_turn: true,
+      rules: RuleSet::default(),
+      castles: [
+        Castle::usual(WHITE_KINGSIDE, geometry),
+        Castle::usual(WHITE_QUEENSIDE, geometry),
+        Castle::usual(BLACK_KINGSIDE, geometry),
+        Castle::usual(BLACK_QUEENSIDE, geometry),
+      ],
+      hash: 0,
+    };
+    board.hash = board.compute_hash();
+    board
+  }
+
+  pub fn geometry(&self) -> Geometry {
+    self.geometry
+  }
+
+  pub fn hash(&self) -> u64 {
+    self.hash
+  }
+
+  fn compute_hash(&self) -> u64 {
+    let mut hash = zobrist::castling(self.castling) ^ zobrist::en_passant(self.en_passant);
+    if !self.white_turn { hash ^= zobrist::black_to_move(); }
+
+    for (i, &piece) in self.pieces.iter().enumerate() {
+      hash ^= zobrist::piece(Loc(i as i32), piece);
+    }
+
+    hash
+  }
+
+  pub fn white_turn(&self) -> bool {
+    self.white_turn
+  }
+
+  pub fn set_white_turn(&mut self, white_turn: bool) {
+    if white_turn != self.white_turn { self.hash ^= zobrist::black_to_move(); }
+    self.white_turn = white_turn;
   }
 
-  fn piece(&self, loc: Loc) -> Piece {
+  pub fn set_rules(&mut self, rules: RuleSet) {
+    self.rules = rules;
+  }
+
+  pub fn piece(&self, loc: Loc) -> Piece {
     self.pieces[loc.0 as usize]
   }
 
+  fn moving_piece(&self, from: Loc, component: Option<u16>) -> Piece {
+    let piece = self.piece(from);
+
+    match component {
+      Some(component) => Piece(piece.color() | component),
+      None => piece,
+    }
+  }
+
+  fn is_promotion(&self, from: Loc, to: Loc, component: Option<u16>) -> bool {
+    let piece = self.moving_piece(from, component);
+    piece.is_pawn() && self.geometry.y(to) == self.geometry.back_row(!piece.is_white())
+  }
+
+  // Whether the move is one step diagonally forward, the only way a pawn can
+  // capture. Hybrids with a pawn in them can reach the en passant square
+  // other ways, which don't capture en passant.
+  fn is_pawn_capture_step(&self, white: bool, from: Loc, to: Loc) -> bool {
+    let dx = self.geometry.x(to) as i32 - self.geometry.x(from) as i32;
+    let dy = self.geometry.y(to) as i32 - self.geometry.y(from) as i32;
+
+    dx.abs() == 1 && dy == if white { -1 } else { 1 }
+  }
+
+  fn kind(&self, from: Loc, to: Loc, component: Option<u16>) -> MoveKind {
+    let piece = self.moving_piece(from, component);
+    let target = self.piece(to);
+
+    if piece.is_pawn() && self.is_pawn_capture_step(piece.is_white(), from, to) && Some(to) == self.en_passant && target.is_empty() {
+      MoveKind::Capture
+    } else if !target.is_empty() {
+      if target.is_white() == piece.is_white() { MoveKind::Merge } else { MoveKind::Capture }
+    } else {
+      MoveKind::Quiet
+    }
+  }
+
+  // Every move for one side, including the ones that leave its king in check.
+  // Castling is kept apart from the king's other moves, since a king that
+  // starts next to or on its castling square could get there either way.
+  fn pseudo_legal_moves(&self, white: bool) -> Vec<Move> {
+    let mut moves = vec![];
+    // Standard boards of orthodox pieces have a faster generator
+    let bitboards = Bitboards::new(self);
+    let piece_moves = |from, piece| match bitboards {
+      Some(ref bitboards) => bitboards.piece_moves_from(from, piece),
+      None => self.piece_moves_from(from, piece),
+    };
+    let split_moves = |from, component| match bitboards {
+      Some(ref bitboards) => bitboards.split_moves_from(from, component),
+      None => self.split_moves_from(from, component),
+    };
+
+    for (from, piece) in self.pieces(white) {
+      let whole = piece_moves(from, piece).into_iter().map(|to| (to, None));
+      let splits = piece.components()
+        .flat_map(|c| split_moves(from, c).unwrap_or_default().into_iter().map(move |to| (to, Some(c))));
+
+      for (to, component) in whole.chain(splits) {
+        if self.is_promotion(from, to, component) {
+          moves.extend(PROMOTIONS.iter().map(|&p| Move{from, to, component, kind: MoveKind::Promotion(p)}));
+        } else {
+          moves.push(Move{from, to, component, kind: self.kind(from, to, component)});
+        }
+      }
+
+      for castle in self.castles.iter().filter(|c| c.king_from == from && self.can_castle(c)) {
+        moves.push(Move{from, to: castle.king_to, component: None, kind: MoveKind::Castle});
+      }
+    }
+
+    moves
+  }
+
+  pub fn captured_by(&self, move_: Move) -> Option<Piece> {
+    let mover = self.moving_piece(move_.from, move_.component);
+    let target = self.piece(move_.to);
+
+    if !target.is_empty() {
+      if target.is_white() != mover.is_white() { Some(target) } else { None }
+    } else if mover.is_pawn() && self.is_pawn_capture_step(mover.is_white(), move_.from, move_.to) && Some(move_.to) == self.en_passant {
+      Some(self.piece(self.geometry.loc(self.geometry.x(move_.to), self.geometry.y(move_.from))))
+    } else {
+      None
+    }
+  }
+
+  pub fn legal_moves(&self, white: bool) -> Vec<Move> {
+    let mut board = self.clone();
+
+    self.pseudo_legal_moves(white).into_iter()
+      .filter(|&m| board.is_legal(m, white))
+      .collect()
+  }
+
+  fn is_legal(&mut self, move_: Move, white: bool) -> bool {
+    let undo = self.make_move(move_);
+    let legal = !self.is_check(white);
+    self.unmake_move(undo);
+    legal
+  }
+
+  // Castles when the king can't otherwise reach `to`
   pub fn move_(&self, from: Loc, to: Loc) -> Self {
-    let mut new_pieces = self.pieces.clone();
+    let castles = self.castles.iter().any(|c| c.king_from == from && c.king_to == to && self.can_castle(c));
+
+    let kind = if self.is_promotion(from, to, None) {
+      MoveKind::Promotion(QUEEN)
+    } else if castles && !self.piece_moves_from(from, self.piece(from)).contains(&to) {
+      MoveKind::Castle
+    } else {
+      self.kind(from, to, None)
+    };
+
+    self.play(Move{from, to, component: None, kind})
+  }
+
+  pub fn play(&self, move_: Move) -> Self {
+    let mut board = self.clone();
+    board.make_move(move_);
+    board
+  }
 
-    let from_piece = std::mem::replace(&mut new_pieces[from.0 as usize], Piece::empty());
-    assert!(!from_piece.is_empty());
-    let to_piece = self.pieces[to.0 as usize];
+  fn set_piece(&mut self, undo: &mut Undo, loc: Loc, piece: Piece) {
+    if !undo.squares[..undo.len].iter().any(|&(l, _)| l == loc) {
+      undo.squares[undo.len] = (loc, self.piece(loc));
+      undo.len += 1;
+    }
+    self.hash ^= zobrist::piece(loc, self.piece(loc)) ^ zobrist::piece(loc, piece);
+    self.pieces[loc.0 as usize] = piece;
+  }
+
+  // A promoting hybrid only trades in its pawn component; whatever else it's
+  // made of comes along unchanged.
+  pub fn make_move(&mut self, move_: Move) -> Undo {
+    let Move{from, to, component, kind} = move_;
+
+    let mut undo = Undo{
+      squares: [(Loc(0), Piece::EMPTY); 4],
+      len: 0,
+      castling: self.castling,
+      en_passant: self.en_passant,
+      white_turn: self.white_turn,
+      hash: self.hash,
+    };
+
+    let piece = self.piece(from);
+    assert!(!piece.is_empty());
+
+    if kind == MoveKind::Castle {
+      self.make_castle(&mut undo, from, to);
+      return undo;
+    }
+
+    let from_piece = self.moving_piece(from, component);
+    if let Some(component) = component {
+      assert!(piece.is_hybrid() && component.count_ones() == 1 && (piece.0 & component) != 0);
+    }
+    let left_behind = Piece(piece.0 & !(from_piece.0 & !COLOR_MASK));
+    let left_behind = if left_behind.0 == piece.color() { Piece::empty() } else { left_behind };
+    self.set_piece(&mut undo, from, left_behind);
 
-    let new_piece = if from_piece.is_white() == to_piece.is_white() {
+    let to_piece = self.piece(to);
+
+    let mut new_piece = if from_piece.is_white() == to_piece.is_white() {
       from_piece.merge(to_piece)
     } else {
       from_piece
     };
-    new_pieces[to.0 as usize] = new_piece;
+    if let MoveKind::Promotion(promotion) = kind {
+      assert!(PROMOTIONS.contains(&promotion));
+      new_piece = Piece((new_piece.0 & !PAWN) | promotion);
+    }
+    self.set_piece(&mut undo, to, new_piece);
+
+    self.en_passant = None;
+    if from_piece.is_pawn() && self.geometry.x(from) == self.geometry.x(to) {
+      // Only for a double move the pawn could have made itself
+      let white = from_piece.is_white();
+      let dy = if white { -1 } else { 1 };
+      if self.geometry.y(from) == self.geometry.pawn_row(white)
+        && self.geometry.y(to) as i32 - self.geometry.y(from) as i32 == dy * 2 {
+        self.en_passant = self.geometry.d(from, 0, dy).filter(|&loc| self.piece(loc).is_empty());
+      }
+    } else if from_piece.is_pawn() && self.is_pawn_capture_step(from_piece.is_white(), from, to) && Some(to) == undo.en_passant && to_piece.is_empty() {
+      // The captured piece sits beside the capturing pawn, on the square it
+      // double-moved to.
+      let victim = self.geometry.loc(self.geometry.x(to), self.geometry.y(from));
+      self.set_piece(&mut undo, victim, Piece::empty());
+    }
+
+    self.finish_move(&undo, from, to, piece.is_white());
+    undo
+  }
+
+  // The king and rook may start on each other's destinations, so both are
+  // lifted off the board before either is put down.
+  fn make_castle(&mut self, undo: &mut Undo, from: Loc, to: Loc) {
+    let castle = *self.castles.iter()
+      .find(|c| c.king_from == from && c.king_to == to && undo.castling & c.right != 0)
+      .expect("No castling right for this move");
+
+    let king = self.piece(castle.king_from);
+    let rook = self.piece(castle.rook_from);
+    self.set_piece(undo, castle.king_from, Piece::empty());
+    self.set_piece(undo, castle.rook_from, Piece::empty());
+    self.set_piece(undo, castle.king_to, king);
+    self.set_piece(undo, castle.rook_to, rook);
+
+    self.en_passant = None;
+    self.finish_move(undo, from, to, king.is_white());
+  }
+
+  // Castling rights, en passant and the turn, which every move updates
+  fn finish_move(&mut self, undo: &Undo, from: Loc, to: Loc, white: bool) {
+    for castle in &self.castles {
+      let touched = [from, to];
+      if touched.contains(&castle.king_from) || touched.contains(&castle.rook_from) {
+        self.castling &= !castle.right;
+      }
+    }
 
-    // TODO: capture
+    self.hash ^= zobrist::castling(undo.castling) ^ zobrist::castling(self.castling);
+    self.hash ^= zobrist::en_passant(undo.en_passant) ^ zobrist::en_passant(self.en_passant);
+    self.set_white_turn(!white);
+  }
 
-    Self{
-      pieces: new_pieces,
+  pub fn unmake_move(&mut self, undo: Undo) {
+    for &(loc, piece) in undo.squares[..undo.len].iter().rev() {
+      self.pieces[loc.0 as usize] = piece;
     }
+    self.castling = undo.castling;
+    self.en_passant = undo.en_passant;
+    self.white_turn = undo.white_turn;
+    self.hash = undo.hash;
   }
 
-  pub fn piece_at(&self, loc: i32) -> u8 {
+  pub fn piece_at(&self, loc: i32) -> u16 {
     self.pieces[loc as usize].0
   }
 
@@ -185,33 +567,136 @@ impl Board {
       .map(|(i, &p)| (Loc(i as i32), p))
   }
 
+  // Whether any of that side's kings is attacked. A side without a king is
+  // never in check.
   pub fn is_check(&self, white: bool) -> bool {
-    let king_loc = self.pieces.iter().position(|p| p.is_king() && (p.is_white() == white)).expect("There should be a king") as i32;
+    if let Some(bitboards) = Bitboards::new(self) { return bitboards.is_check(white); }
+
+    self.pieces(white).any(|(loc, p)| p.is_king() && self.is_square_attacked(loc, !white))
+  }
+
+  pub fn is_square_attacked(&self, loc: Loc, by_white: bool) -> bool {
+    !self.attackers_of(loc, by_white).is_empty()
+  }
+
+  // Every piece of one side that could capture on `loc`, found by looking
+  // outwards from `loc` with each component's attack pattern reversed. Only
+  // the diagonals count for pawns, and whatever is on `loc` itself doesn't
+  // matter.
+  pub fn attackers_of(&self, loc: Loc, white: bool) -> Vec<Loc> {
+    let mut attackers = vec![];
+
+    {
+      let mut check = |from: Option<Loc>, component: u16| {
+        if let Some(from) = from {
+          let piece = self.piece(from);
+          if !piece.is_empty() && piece.is_white() == white && piece.0 & component != 0 {
+            attackers.push(from);
+          }
+        }
+      };
+
+      // Every pattern is symmetric, so an attacker is found by making its
+      // moves backwards from `loc`.
+      for piece_type in REGISTRY.iter() {
+        for &movement in piece_type.movements {
+          match movement {
+            Movement::Leap(steps) => {
+              for &(dx, dy) in steps {
+                check(self.geometry.d(loc, dx, dy), piece_type.component);
+              }
+            },
+            Movement::Ride(steps) => {
+              for &(dx, dy) in steps {
+                let mut from = loc;
+                while let Some(next) = self.geometry.d(from, dx, dy) {
+                  from = next;
+                  if self.piece(from).is_empty() { continue; }
+
+                  check(Some(from), piece_type.component);
+                  break;
+                }
+              }
+            },
+            Movement::Pawn => {
+              // A white pawn attacks towards -y, so it is found one row below.
+              let pawn_dy = if white { 1 } else { -1 };
+              for &dx in &[-1, 1] {
+                check(self.geometry.d(loc, dx, pawn_dy), piece_type.component);
+              }
+            },
+          }
+        }
+      }
+    }
+
+    attackers.sort();
+    attackers.dedup();
+    attackers
+  }
+
+  fn can_castle(&self, castle: &Castle) -> bool {
+    if self.castling & castle.right == 0 { return false; }
+
+    let color = if castle.white { WHITE } else { BLACK };
+    if self.piece(castle.king_from) != Piece(color | KING) { return false; }
+    if self.piece(castle.rook_from) != Piece(color | ROOK) { return false; }
 
-    self.pieces(!white)
-      .any(|(i, _)| self.moves_from(i).unwrap().into_iter().any(|l| l.0 == king_loc))
+    // Everything either of them passes over or lands on has to be empty
+    // apart from the two of them.
+    let path = castle.king_path().chain(castle.rook_path());
+    if path.filter(|&loc| loc != castle.king_from && loc != castle.rook_from).any(|loc| !self.piece(loc).is_empty()) { return false; }
+
+    !castle.king_path().any(|loc| self.is_square_attacked(loc, !castle.white))
   }
 
   pub fn is_check_mate(&self, white: bool) -> bool {
     if !self.is_check(white) { return false }
 
-    self.pieces(white)
-      .all(|(i, _)| {
-        let moves = self.moves_from(i).unwrap();
-
-        moves.into_iter().all(|to| self.move_(i, to).is_check(white))
-      })
+    !self.has_legal_move(white)
   }
 
   pub fn is_stale_mate(&self, white: bool) -> bool {
     if self.is_check(white) { return false }
 
-    self.pieces(white)
-      .all(|(i, _)| {
-        let moves = self.moves_from(i).unwrap();
+    !self.has_legal_move(white)
+  }
+
+  // Whether neither side can ever mate. This goes by components rather than
+  // pieces: a lone bishop+knight hybrid can mate where a lone bishop or knight
+  // can't, and a king that has absorbed a minor piece still carries it.
+  pub fn is_insufficient_material(&self) -> bool {
+    let mut minors = 0;
+    let mut bishop_colors = 0;
+    let mut only_bishops = true;
+
+    for (loc, piece) in self.pieces(true).chain(self.pieces(false)) {
+      if piece.is_king() && !piece.is_hybrid() { continue; }
+      if piece.is_queen() || piece.is_rook() || piece.is_pawn() { return false; }
+      if piece.is_bishop() && piece.is_knight() { return false; }
+      // Not worked out for fairy pieces, so they're assumed to be able to
+      if piece.0 & (CAMEL | ZEBRA | NIGHTRIDER) != 0 { return false; }
+
+      if piece.is_bishop() && !piece.is_hybrid() {
+        bishop_colors |= 1 << ((self.geometry.x(loc) + self.geometry.y(loc)) % 2);
+      } else {
+        only_bishops = false;
+      }
+      minors += 1;
+    }
 
-        moves.into_iter().all(|to| self.move_(i, to).is_check(white))
-      })
+    // Bishops that all stand on the same color can't mate no matter how many
+    // there are.
+    minors <= 1 || (only_bishops && bishop_colors != 3)
+  }
+
+  // Split moves count too: leaving part of a pinned hybrid behind can be the
+  // only way to get out of check.
+  fn has_legal_move(&self, white: bool) -> bool {
+    let mut board = self.clone();
+
+    self.pseudo_legal_moves(white).into_iter()
+      .any(|m| board.is_legal(m, white))
   }
 
   pub fn moves_from(&self, loc: Loc) -> Option<Vec<Loc>> {
@@ -219,120 +704,111 @@ impl Board {
 
     if piece.is_empty() { return None };
 
-    let mut dests = vec![];
-
-    if piece.is_pawn() {
-      let dy = if piece.is_white() { -1 } else { 1 };
+    let mut dests = self.piece_moves_from(loc, piece);
 
-      // Forward movement
-      if let Some(new_loc) = loc.d(0, dy) {
-        if self.piece(new_loc).is_empty() {
-          dests.push(new_loc);
-
-          // Double move from starting position
-          let starting_row = if piece.is_white() { 6 } else { 1 };
-          if starting_row == loc.y() {
-            if let Some(new_loc) = loc.d(0, dy * 2) {
-              if self.piece(new_loc).is_empty() {
-                dests.push(new_loc);
-              }
-            }
-          }
+    if piece.is_king() {
+      for castle in self.castles.iter().filter(|c| c.king_from == loc) {
+        if self.can_castle(castle) {
+          dests.push(castle.king_to);
         }
       }
 
-      // Attack
-      for &dx in &[-1, 1] {
-        if let Some(new_loc) = loc.d(dx, dy) {
-          if !self.piece(new_loc).is_empty() {
-            dests.push(new_loc);
-          }
-        }
-      }
-    }
-    if piece.is_rook() {
-      let ds = [(1, 0), (-1, 0), (0, 1), (0, -1)];
-
-      for &(dx, dy) in &ds {
-        let mut loc = loc;
-
-        loop {
-          if let Some(new_loc) = loc.d(dx, dy) {
-            loc = new_loc;
-            dests.push(loc);
-            if !self.piece(new_loc).is_empty() { break; }
-          } else {
-            break
-          }
-        }
-      }
+      dests.sort();
+      dests.dedup();
     }
-    if piece.is_bishop() {
-      let ds = [(1, 1), (-1, 1), (1, -1), (-1, -1)];
 
-      for &(dx, dy) in &ds {
-        let mut loc = loc;
+    Some(dests)
+  }
 
-        loop {
-          if let Some(new_loc) = loc.d(dx, dy) {
-            loc = new_loc;
-            dests.push(new_loc);
-            if !self.piece(new_loc).is_empty() { break; }
-          } else {
-            break
-          }
+  // Destinations for just one component of a hybrid, as if it stood alone.
+  pub fn split_moves_from(&self, loc: Loc, component: u16) -> Option<Vec<Loc>> {
+    let piece = self.piece(loc);
+
+    if !self.rules.splits || !piece.is_hybrid() || (piece.0 & component) == 0 { return None };
+
+    Some(self.piece_moves_from(loc, Piece(piece.color() | component)))
+  }
+
+  // Every destination except castling, which can never capture and so is left
+  // out when looking for attacked squares.
+  fn piece_moves_from(&self, loc: Loc, piece: Piece) -> Vec<Loc> {
+    let mut dests = vec![];
+
+    for component in piece.components() {
+      for &movement in registry::piece_type(component).movements {
+        match movement {
+          Movement::Leap(steps) => {
+            dests.extend(steps.iter().filter_map(|&(dx, dy)| self.geometry.d(loc, dx, dy)));
+          },
+          Movement::Ride(steps) => {
+            for &(dx, dy) in steps {
+              let mut loc = loc;
+
+              while let Some(new_loc) = self.geometry.d(loc, dx, dy) {
+                loc = new_loc;
+                dests.push(loc);
+                if !self.piece(new_loc).is_empty() { break; }
+              }
+            }
+          },
+          Movement::Pawn => self.pawn_moves_from(loc, piece.is_white(), &mut dests),
         }
       }
     }
-    if piece.is_queen() {
-      let ds = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (-1, 1), (1, -1), (-1, -1)];
-
-      for &(dx, dy) in &ds {
-        let mut loc = loc;
-
-        loop {
-          if let Some(new_loc) = loc.d(dx, dy) {
-            loc = new_loc;
-            dests.push(loc);
-            if !self.piece(new_loc).is_empty() { break; }
-          } else {
-            break
+
+    dests.retain(|&to| {
+      let target = self.piece(to);
+      target.is_empty() || target.is_white() != piece.is_white() || self.rules.allows_merge(piece, target)
+    });
+
+    dests.sort();
+    dests.dedup();
+
+    dests
+  }
+
+  fn pawn_moves_from(&self, loc: Loc, white: bool, dests: &mut Vec<Loc>) {
+    let dy = if white { -1 } else { 1 };
+
+    // Forward movement
+    if let Some(new_loc) = self.geometry.d(loc, 0, dy) {
+      if self.piece(new_loc).is_empty() {
+        dests.push(new_loc);
+
+        // Double move from starting position
+        if self.geometry.pawn_row(white) == self.geometry.y(loc) {
+          if let Some(new_loc) = self.geometry.d(loc, 0, dy * 2) {
+            if self.piece(new_loc).is_empty() {
+              dests.push(new_loc);
+            }
           }
         }
       }
     }
-    if piece.is_king() {
-      let ds = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (-1, 1), (1, -1), (-1, -1)];
 
-      for &(dx, dy) in &ds {
-        if let Some(new_loc) = loc.d(dx, dy) {
-          dests.push(new_loc);
-        }
-      }
-    }
-    if piece.is_knight() {
-      let ds = [(1, 2), (2, 1),
-                (-1, 2), (2, -1),
-                (1, -2), (-2, 1),
-                (-1, -2), (-2, -1)];
-
-      for &(dx, dy) in &ds {
-        if let Some(new_loc) = loc.d(dx, dy) {
+    // Attack
+    for &dx in &[-1, 1] {
+      if let Some(new_loc) = self.geometry.d(loc, dx, dy) {
+        if !self.piece(new_loc).is_empty() {
           dests.push(new_loc);
+        } else if Some(new_loc) == self.en_passant {
+          let victim = self.piece(self.geometry.d(loc, dx, 0).unwrap());
+          if !victim.is_empty() && victim.is_white() != white {
+            dests.push(new_loc);
+          }
         }
       }
     }
-
-    dests.sort();
-    dests.dedup();
-
-    Some(dests)
   }
 }
 
 #[cfg(test)]
 mod test {
-  use super::{Board,Piece};
+  use super::{Board,Geometry,Loc,Move,MoveKind,Piece,RuleSet,WHITE_KINGSIDE,WHITE_QUEENSIDE,BLACK_KINGSIDE,BLACK_QUEENSIDE};
+  use super::{WHITE,KING,QUEEN,ROOK,BISHOP,KNIGHT,PAWN,CAMEL,ZEBRA};
+  use super::geometry::loc;
+
+  const ALL_CASTLING: u8 = WHITE_KINGSIDE | WHITE_QUEENSIDE | BLACK_KINGSIDE | BLACK_QUEENSIDE;
 
   #[test]
   pub fn check() {
@@ -348,6 +824,28 @@ mod test {
     assert!(board.is_check(false));
   }
 
+  #[test]
+  pub fn attackers() {
+    let mut board = empty_board();
+
+    board.pieces[4 + 4*8] = Piece::BLACK_KING;
+    board.pieces[4 + 5*8] = Piece::WHITE_PAWN;
+    assert!(!board.is_check(false));
+
+    board.pieces[3 + 5*8] = Piece::WHITE_PAWN;
+    board.pieces[4 + 0*8] = Piece(WHITE | ROOK | KNIGHT);
+    board.pieces[5 + 6*8] = Piece(WHITE | BISHOP | KNIGHT);
+    board.pieces[7 + 1*8] = Piece::WHITE_BISHOP;
+    board.pieces[6 + 2*8] = Piece::BLACK_PAWN;
+    assert!(board.attackers_of(Loc(4 + 4*8), true) == vec![Loc(4 + 0*8), Loc(3 + 5*8), Loc(5 + 6*8)]);
+    assert!(board.is_check(false));
+
+    // Black pawns attack towards +y
+    assert!(board.is_square_attacked(Loc(7 + 3*8), false));
+    assert!(!board.is_square_attacked(Loc(6 + 3*8), false));
+    assert!(board.attackers_of(Loc(0 + 7*8), false).is_empty());
+  }
+
   #[test]
   pub fn check_mate() {
     let mut board = empty_board();
@@ -376,7 +874,322 @@ mod test {
     assert!(board.is_stale_mate(false));
   }
 
+  #[test]
+  pub fn castling() {
+    let mut board = empty_board();
+
+    board.pieces[4 + 0*8] = Piece::BLACK_KING;
+    board.pieces[4 + 7*8] = Piece::WHITE_KING;
+    board.pieces[0 + 7*8] = Piece::WHITE_ROOK;
+    board.pieces[7 + 7*8] = Piece::WHITE_ROOK;
+    board.castling = ALL_CASTLING;
+
+    let moves = board.moves_from(Loc(4 + 7*8)).unwrap();
+    assert!(moves.contains(&Loc(6 + 7*8)));
+    assert!(moves.contains(&Loc(2 + 7*8)));
+
+    let castled = board.move_(Loc(4 + 7*8), Loc(6 + 7*8));
+    assert!(castled.piece(Loc(6 + 7*8)) == Piece::WHITE_KING);
+    assert!(castled.piece(Loc(5 + 7*8)) == Piece::WHITE_ROOK);
+    assert!(castled.piece(Loc(7 + 7*8)).is_empty());
+    assert_eq!(castled.castling & (WHITE_KINGSIDE | WHITE_QUEENSIDE), 0);
+
+    // Can't castle through an attacked square
+    board.pieces[5 + 0*8] = Piece::BLACK_ROOK;
+    let moves = board.moves_from(Loc(4 + 7*8)).unwrap();
+    assert!(!moves.contains(&Loc(6 + 7*8)));
+    assert!(moves.contains(&Loc(2 + 7*8)));
+  }
+
+  #[test]
+  pub fn castling_rights_lost_on_merge() {
+    let mut board = empty_board();
+
+    board.pieces[4 + 0*8] = Piece::BLACK_KING;
+    board.pieces[4 + 7*8] = Piece::WHITE_KING;
+    board.pieces[7 + 7*8] = Piece::WHITE_ROOK;
+    board.pieces[7 + 5*8] = Piece::WHITE_KNIGHT;
+    board.castling = ALL_CASTLING;
+
+    let merged = board.move_(Loc(7 + 5*8), Loc(7 + 7*8));
+    assert!(merged.piece(Loc(7 + 7*8)).is_hybrid());
+    assert!(!merged.moves_from(Loc(4 + 7*8)).unwrap().contains(&Loc(6 + 7*8)));
+  }
+
+  #[test]
+  pub fn en_passant() {
+    let mut board = empty_board();
+
+    board.pieces[4 + 0*8] = Piece::BLACK_KING;
+    board.pieces[4 + 7*8] = Piece::WHITE_KING;
+    board.pieces[3 + 1*8] = Piece::BLACK_PAWN;
+    board.pieces[4 + 3*8] = Piece::WHITE_PAWN;
+
+    let board = board.move_(Loc(3 + 1*8), Loc(3 + 3*8));
+    assert!(board.en_passant == Some(Loc(3 + 2*8)));
+    assert!(board.moves_from(Loc(4 + 3*8)).unwrap().contains(&Loc(3 + 2*8)));
+
+    let captured = board.move_(Loc(4 + 3*8), Loc(3 + 2*8));
+    assert!(captured.piece(Loc(3 + 2*8)) == Piece::WHITE_PAWN);
+    assert!(captured.piece(Loc(3 + 3*8)).is_empty());
+
+    let declined = board.move_(Loc(4 + 7*8), Loc(4 + 6*8));
+    assert!(declined.en_passant.is_none());
+  }
+
+  #[test]
+  pub fn en_passant_escapes_mate() {
+    let mut board = empty_board();
+
+    board.pieces[7 + 0*8] = Piece::BLACK_KING;
+    board.pieces[1 + 1*8] = Piece::BLACK_PAWN;
+    board.pieces[0 + 2*8] = Piece::BLACK_PAWN;
+    board.pieces[2 + 2*8] = Piece::BLACK_KNIGHT;
+    board.pieces[7 + 5*8] = Piece::BLACK_ROOK;
+    board.pieces[0 + 4*8] = Piece::WHITE_KING;
+    board.pieces[2 + 3*8] = Piece::WHITE_PAWN;
+
+    let mut board = board.move_(Loc(1 + 1*8), Loc(1 + 3*8));
+    assert!(board.is_check(true));
+    assert!(!board.is_check_mate(true));
+
+    board.en_passant = None;
+    assert!(board.is_check_mate(true));
+  }
+
+  #[test]
+  pub fn en_passant_hybrids() {
+    // Landing on the en passant square other than by a pawn's diagonal step
+    // doesn't capture
+    let board = Board::from_fen("4k3/3p4/8/8/2[NP]Q4/8/8/4K3 b - -").unwrap().move_(loc("d7"), loc("d5"));
+    assert!(board.en_passant == Some(loc("d6")));
+    let knight = board.parse_lan("c4d6").unwrap();
+    assert!(knight.kind == MoveKind::Quiet && board.captured_by(knight).is_none());
+    assert_eq!(board.san(knight), "[NP]d6+");
+    let after = board.play(knight);
+    assert!(after.piece(loc("d4")) == Piece(WHITE | QUEEN) && !after.piece(loc("d5")).is_empty());
+
+    let board = Board::from_fen("4k3/3p4/8/8/1[BP]6/8/8/4K3 b - -").unwrap().move_(loc("d7"), loc("d5"));
+    let bishop = board.parse_lan("b4d6").unwrap();
+    assert!(bishop.kind == MoveKind::Quiet && board.captured_by(bishop).is_none());
+    assert!(!board.play(bishop).piece(loc("d5")).is_empty());
+
+    // ... but the pawn in a hybrid still can
+    let board = Board::from_fen("4k3/3p4/8/4[NP]3/8/8/8/4K3 b - -").unwrap().move_(loc("d7"), loc("d5"));
+    let pawn = board.parse_lan("e5d6").unwrap();
+    assert!(pawn.kind == MoveKind::Capture && board.play(pawn).piece(loc("d5")).is_empty());
+
+    // Only a double move from the pawn's starting row gives an en passant
+    // square
+    let board = Board::from_fen("4k3/8/8/1p6/8/[RP]7/8/4K3 w - -").unwrap().move_(loc("a3"), loc("a5"));
+    assert!(board.en_passant.is_none());
+    assert!(board.parse_lan("b5a4").is_err());
+    let board = Board::from_fen("4k3/8/8/8/1p6/8/[RP]7/4K3 w - -").unwrap().move_(loc("a2"), loc("a4"));
+    assert!(board.en_passant == Some(loc("a3")));
+  }
+
+  #[test]
+  pub fn promotion() {
+    let mut board = empty_board();
+
+    board.pieces[4 + 0*8] = Piece::BLACK_KING;
+    board.pieces[4 + 7*8] = Piece::WHITE_KING;
+    board.pieces[0 + 1*8] = Piece::WHITE_PAWN;
+    board.pieces[7 + 1*8] = Piece(WHITE | PAWN | ROOK);
+
+    let promotions = board.legal_moves(true).into_iter()
+      .filter(|m| m.from == Loc(0 + 1*8))
+      .collect::<Vec<_>>();
+    assert_eq!(promotions.len(), 4);
+    assert!(promotions.iter().all(|m| matches!(m.kind, MoveKind::Promotion(_))));
+
+    assert!(board.move_(Loc(0 + 1*8), Loc(0 + 0*8)).piece(Loc(0 + 0*8)) == Piece::WHITE_QUEEN);
+    let knight = Move{from: Loc(0 + 1*8), to: Loc(0 + 0*8), component: None, kind: MoveKind::Promotion(KNIGHT)};
+    assert!(board.play(knight).piece(Loc(0 + 0*8)) == Piece::WHITE_KNIGHT);
+
+    // Hybrids keep their other components
+    let bishop = Move{from: Loc(7 + 1*8), to: Loc(7 + 0*8), component: None, kind: MoveKind::Promotion(BISHOP)};
+    assert!(board.play(bishop).piece(Loc(7 + 0*8)) == Piece(WHITE | ROOK | BISHOP));
+  }
+
+  #[test]
+  pub fn split() {
+    let mut board = empty_board();
+
+    board.pieces[4 + 0*8] = Piece::BLACK_KING;
+    board.pieces[4 + 7*8] = Piece::WHITE_KING;
+    board.pieces[0 + 7*8] = Piece(WHITE | ROOK | KNIGHT);
+
+    assert!(board.split_moves_from(Loc(0 + 7*8), KNIGHT).unwrap() == vec![Loc(1 + 5*8), Loc(2 + 6*8)]);
+    assert!(board.split_moves_from(Loc(0 + 7*8), BISHOP).is_none());
+
+    let split = board.play(Move{from: Loc(0 + 7*8), to: Loc(1 + 5*8), component: Some(KNIGHT), kind: MoveKind::Quiet});
+    assert!(split.piece(Loc(0 + 7*8)) == Piece::WHITE_ROOK);
+    assert!(split.piece(Loc(1 + 5*8)) == Piece::WHITE_KNIGHT);
+  }
+
+  #[test]
+  pub fn split_escapes_mate() {
+    let mut board = empty_board();
+
+    // The hybrid on b2 is pinned, but either of its components can block
+    board.pieces[0 + 7*8] = Piece::WHITE_KING;
+    board.pieces[1 + 6*8] = Piece(WHITE | ROOK | KNIGHT);
+    board.pieces[0 + 0*8] = Piece::BLACK_ROOK;
+    board.pieces[7 + 0*8] = Piece::BLACK_BISHOP;
+    board.pieces[3 + 6*8] = Piece::BLACK_KNIGHT;
+    board.pieces[7 + 7*8] = Piece::BLACK_KING;
+
+    assert!(board.moves_from(Loc(1 + 6*8)).unwrap().into_iter().all(|to| board.move_(Loc(1 + 6*8), to).is_check(true)));
+    assert!(board.is_check(true));
+    assert!(!board.is_check_mate(true));
+  }
+
+  #[test]
+  pub fn rules() {
+    let mut board = empty_board();
+
+    board.pieces[4 + 0*8] = Piece::BLACK_KING;
+    board.pieces[4 + 7*8] = Piece::WHITE_KING;
+    board.pieces[3 + 7*8] = Piece(WHITE | ROOK | KNIGHT);
+    board.pieces[3 + 6*8] = Piece::WHITE_BISHOP;
+    board.pieces[4 + 6*8] = Piece::WHITE_PAWN;
+    assert!(board.moves_from(Loc(4 + 7*8)).unwrap().contains(&Loc(3 + 7*8)));
+    assert!(board.moves_from(Loc(3 + 7*8)).unwrap().contains(&Loc(3 + 6*8)));
+
+    board.rules = RuleSet::NO_KING_MERGES;
+    assert!(!board.moves_from(Loc(4 + 7*8)).unwrap().contains(&Loc(3 + 7*8)));
+    assert!(board.moves_from(Loc(3 + 7*8)).unwrap().contains(&Loc(3 + 6*8)));
+
+    board.rules = RuleSet::PAIRS;
+    assert!(board.moves_from(Loc(4 + 7*8)).unwrap().contains(&Loc(4 + 6*8)));
+    assert!(!board.moves_from(Loc(3 + 7*8)).unwrap().contains(&Loc(3 + 6*8)));
+
+    board.rules = RuleSet::STRICT;
+    assert!(!board.moves_from(Loc(3 + 6*8)).unwrap().contains(&Loc(4 + 7*8)));
+    assert!(!board.moves_from(Loc(4 + 7*8)).unwrap().contains(&Loc(4 + 6*8)));
+
+    // Classic rules have no limit on how big a hybrid gets
+    board.rules = RuleSet::CLASSIC;
+    board.pieces[3 + 7*8] = Piece(WHITE | QUEEN | ROOK | KNIGHT | PAWN | CAMEL | ZEBRA);
+    assert!(board.moves_from(Loc(3 + 7*8)).unwrap().contains(&Loc(3 + 6*8)));
+  }
+
+  #[test]
+  pub fn legal_moves() {
+    // The usual 20, plus 20 merges into neighbouring pieces
+    let board = Board::fresh();
+    assert_eq!(board.legal_moves(true).len(), 40);
+    assert_eq!(board.legal_moves(false).len(), 40);
+
+    let mut board = empty_board();
+
+    board.pieces[4 + 0*8] = Piece::BLACK_KING;
+    board.pieces[4 + 7*8] = Piece::WHITE_KING;
+    board.pieces[7 + 7*8] = Piece::WHITE_ROOK;
+    board.pieces[7 + 6*8] = Piece::WHITE_KNIGHT;
+    board.pieces[7 + 1*8] = Piece::BLACK_PAWN;
+    board.castling = ALL_CASTLING;
+
+    let moves = board.legal_moves(true);
+    let kind = |from: i32, to: i32| moves.iter().find(|m| m.from == Loc(from) && m.to == Loc(to) && m.component.is_none()).map(|m| m.kind);
+    assert!(kind(4 + 7*8, 6 + 7*8) == Some(MoveKind::Castle));
+    assert!(kind(4 + 7*8, 4 + 6*8) == Some(MoveKind::Quiet));
+    assert!(kind(7 + 7*8, 7 + 6*8) == Some(MoveKind::Merge));
+    assert!(kind(7 + 6*8, 6 + 4*8) == Some(MoveKind::Quiet));
+
+    let board = board.move_(Loc(7 + 7*8), Loc(7 + 6*8));
+    let moves = board.legal_moves(true);
+    assert!(moves.iter().any(|m| m.from == Loc(7 + 6*8) && m.to == Loc(7 + 1*8) && m.component.is_none() && m.kind == MoveKind::Capture));
+    assert!(moves.iter().any(|m| m.from == Loc(7 + 6*8) && m.to == Loc(7 + 1*8) && m.component == Some(ROOK) && m.kind == MoveKind::Capture));
+    assert!(!moves.iter().any(|m| m.from == Loc(4 + 7*8) && m.kind == MoveKind::Castle));
+  }
+
+  #[test]
+  pub fn make_unmake() {
+    let mut board = empty_board();
+
+    board.pieces[4 + 0*8] = Piece::BLACK_KING;
+    board.pieces[4 + 7*8] = Piece::WHITE_KING;
+    board.pieces[0 + 7*8] = Piece::WHITE_ROOK;
+    board.pieces[7 + 7*8] = Piece(WHITE | ROOK | KNIGHT);
+    board.pieces[2 + 1*8] = Piece(WHITE | PAWN | BISHOP);
+    board.pieces[3 + 1*8] = Piece::BLACK_PAWN;
+    board.pieces[5 + 3*8] = Piece::WHITE_PAWN;
+    board.pieces[6 + 1*8] = Piece::BLACK_PAWN;
+    board.castling = ALL_CASTLING;
+    let board = board.move_(Loc(6 + 1*8), Loc(6 + 3*8));
+
+    let mut scratch = board.clone();
+    for move_ in board.legal_moves(true) {
+      let undo = scratch.make_move(move_);
+      assert!(scratch != board);
+      scratch.unmake_move(undo);
+      assert!(scratch == board);
+    }
+  }
+
+  #[test]
+  pub fn insufficient_material() {
+    let mut board = empty_board();
+
+    board.pieces[4 + 0*8] = Piece::BLACK_KING;
+    board.pieces[4 + 7*8] = Piece::WHITE_KING;
+    assert!(board.is_insufficient_material());
+
+    board.pieces[2 + 7*8] = Piece::WHITE_BISHOP;
+    assert!(board.is_insufficient_material());
+
+    board.pieces[1 + 7*8] = Piece::WHITE_KNIGHT;
+    assert!(!board.is_insufficient_material());
+
+    board.pieces[1 + 7*8] = Piece::EMPTY;
+    board.pieces[2 + 7*8] = Piece(WHITE | BISHOP | KNIGHT);
+    assert!(!board.is_insufficient_material());
+
+    board.pieces[2 + 7*8] = Piece::EMPTY;
+    board.pieces[4 + 7*8] = Piece(WHITE | KING | KNIGHT);
+    assert!(board.is_insufficient_material());
+    board.pieces[2 + 0*8] = Piece::BLACK_BISHOP;
+    assert!(!board.is_insufficient_material());
+
+    board.pieces[4 + 7*8] = Piece::WHITE_KING;
+    board.pieces[5 + 7*8] = Piece::WHITE_BISHOP;
+    assert!(board.is_insufficient_material());
+    board.pieces[5 + 7*8] = Piece::WHITE_ROOK;
+    assert!(!board.is_insufficient_material());
+  }
+
+  #[test]
+  pub fn hash() {
+    let mut board = Board::fresh();
+    let start = board.hash();
+
+    let moves = [(6 + 7*8, 5 + 5*8), (4 + 1*8, 4 + 3*8), (5 + 5*8, 4 + 3*8), (3 + 0*8, 6 + 3*8)];
+    for &(from, to) in &moves {
+      board = board.move_(Loc(from), Loc(to));
+      assert_eq!(board.hash(), board.compute_hash());
+      assert!(board.hash() != start);
+    }
+
+    // Same pieces, different side to move
+    let mut knights = Board::fresh();
+    for &(from, to) in &[(6 + 7*8, 5 + 5*8), (6 + 0*8, 5 + 2*8), (5 + 5*8, 6 + 7*8), (5 + 2*8, 6 + 0*8)] {
+      knights = knights.move_(Loc(from), Loc(to));
+    }
+    assert_eq!(knights.hash(), start);
+    knights.set_white_turn(false);
+    assert!(knights.hash() != start);
+
+    // Every hybrid gets its own key
+    let mut hybrid = Board::empty();
+    hybrid.pieces[0] = Piece(WHITE | ROOK | KNIGHT);
+    let mut other = Board::empty();
+    other.pieces[0] = Piece(WHITE | ROOK | BISHOP);
+    assert!(hybrid.compute_hash() != other.compute_hash());
+  }
+
   fn empty_board() -> Board {
-    Board{pieces: [Piece::EMPTY; 64]}
+    Board::empty()
   }
 }
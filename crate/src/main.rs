@@ -0,0 +1,236 @@
+extern crate hybrid_chess;
+
+use std::env;
+use std::io::{self,BufRead,Write};
+use std::process;
+
+use hybrid_chess::{ai,Board,Draw,Game,GameResult,Move,NotationError,Piece,RuleSet};
+
+// Plays in the terminal instead of the browser, for playing and debugging
+// without one. Moves can be given in either long or standard algebraic
+// notation.
+
+const USAGE: &str = "Usage: hybrid-chess [play-as-white|play-as-black|human-vs-human|ai-vs-ai] [--ascii] [--rules PRESET] [--fen FEN]";
+
+const HELP: &str = "Enter a move like e2e4 or Nf3, or one of:
+  moves   list the legal moves
+  fen     show the position as a FEN
+  quit    stop playing";
+
+// Whether the AI plays white and black, as on the browser's setup screen
+const MODES: [(&str, bool, bool); 4] = [
+  ("play-as-white", false, true),
+  ("play-as-black", true, false),
+  ("human-vs-human", false, false),
+  ("ai-vs-ai", true, true),
+];
+
+struct Options {
+  white_ai: bool,
+  black_ai: bool,
+  ascii: bool,
+  board: Board,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+  let mut mode = MODES[0];
+  let mut ascii = false;
+  let mut rules = RuleSet::default();
+  let mut fen = None;
+
+  let mut args = args.iter();
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--ascii" => ascii = true,
+      "--rules" => {
+        let name = args.next().ok_or("--rules needs a preset")?;
+        rules = RuleSet::preset(name).ok_or_else(|| format!("'{}' isn't a rules preset", name))?;
+      },
+      "--fen" => fen = Some(args.next().ok_or("--fen needs a position")?),
+      name => mode = *MODES.iter().find(|&&(m, _, _)| m == name).ok_or_else(|| format!("'{}' isn't an option", name))?,
+    }
+  }
+
+  let mut board = match fen {
+    Some(fen) => Board::from_fen(fen).map_err(|error| format!("Bad FEN: {}", error))?,
+    None => Board::fresh(),
+  };
+  board.set_rules(rules);
+
+  // Only positions that could come up in a game, as with the browser's editor
+  let problems = board.validate().iter().map(|problem| problem.to_string()).collect::<Vec<_>>();
+  if !problems.is_empty() { return Err(format!("Can't start from this FEN: {}", problems.join(", "))); }
+
+  let (_, white_ai, black_ai) = mode;
+  Ok(Options{ white_ai, black_ai, ascii, board })
+}
+
+// Orthodox pieces on their own get their chess symbols, and anything else is
+// written as in a FEN, with hybrids in brackets
+fn square(piece: Piece, ascii: bool) -> String {
+  if piece.is_empty() { return if ascii { "." } else { "·" }.to_owned(); }
+
+  let fen = piece.to_fen();
+  if ascii { return fen; }
+
+  let symbol = match fen.as_str() {
+    "K" => '♔', "Q" => '♕', "R" => '♖', "B" => '♗', "N" => '♘', "P" => '♙',
+    "k" => '♚', "q" => '♛', "r" => '♜', "b" => '♝', "n" => '♞', "p" => '♟',
+    _ => return fen,
+  };
+  symbol.to_string()
+}
+
+// Every column is as wide as the widest hybrid on the board
+fn render(board: &Board, ascii: bool) -> String {
+  let geometry = board.geometry();
+  let squares = geometry.squares().map(|loc| square(board.piece(loc), ascii)).collect::<Vec<_>>();
+  let width = squares.iter().map(|s| s.chars().count()).max().unwrap_or(1);
+  let pad = |s: &str| format!("{:<1$}", s, width);
+
+  let mut rendered = String::new();
+  for (y, rank) in squares.chunks(geometry.width()).enumerate() {
+    let rank = rank.iter().map(|s| pad(s)).collect::<Vec<_>>().join(" ");
+    rendered += &format!("{:>2} {}\n", geometry.height() - y, rank.trim_end());
+  }
+
+  let files = (0..geometry.width()).map(|x| pad(&((b'a' + x as u8) as char).to_string())).collect::<Vec<_>>();
+  rendered += &format!("   {}\n", files.join(" ").trim_end());
+
+  rendered
+}
+
+// Long algebraic first, so `b1c3` isn't taken for something else
+fn parse_move(board: &Board, text: &str) -> Result<Move, NotationError> {
+  board.parse_lan(text).or_else(|_| board.parse_san(text))
+}
+
+fn describe(result: GameResult) -> String {
+  match result {
+    GameResult::Checkmate(white) => format!("Checkmate! {} wins", if white { "White" } else { "Black" }),
+    GameResult::Draw(Draw::Stalemate) => "Stalemate!".to_owned(),
+    GameResult::Draw(draw) => draw.description().to_owned(),
+  }
+}
+
+// None once the player quits or input runs out
+fn read_move<I: Iterator<Item=io::Result<String>>>(game: &Game, lines: &mut I) -> Option<Move> {
+  let side = if game.white_turn() { "White" } else { "Black" };
+
+  loop {
+    print!("{} to move: ", side);
+    io::stdout().flush().ok();
+
+    let line = match lines.next() {
+      Some(Ok(line)) => line,
+      _ => return None,
+    };
+
+    match line.trim() {
+      "" => {},
+      "quit" => return None,
+      "help" => println!("{}", HELP),
+      "fen" => println!("{}", game.board().to_fen()),
+      "moves" => {
+        let board = game.board();
+        let moves = game.legal_moves().into_iter().map(|move_| board.san(move_)).collect::<Vec<_>>();
+        println!("{}", moves.join(" "));
+      },
+      text => match parse_move(game.board(), text) {
+        Ok(move_) => return Some(move_),
+        Err(error) => println!("{}. Type 'help' for help.", error),
+      },
+    }
+  }
+}
+
+fn main() {
+  let args = env::args().skip(1).collect::<Vec<_>>();
+  let options = parse_args(&args).unwrap_or_else(|error| {
+    eprintln!("{}\n{}", error, USAGE);
+    process::exit(2);
+  });
+
+  let white_turn = options.board.white_turn();
+  let mut game = Game::from_board(options.board, white_turn);
+  let stdin = io::stdin();
+  let mut lines = stdin.lock().lines();
+
+  loop {
+    println!("\n{}", render(game.board(), options.ascii));
+    if let Some(result) = game.result() {
+      println!("{}", describe(result));
+      return;
+    }
+
+    let white = game.white_turn();
+    let move_ = if (white && options.white_ai) || (!white && options.black_ai) {
+      let move_ = ai::choose_minimax(&game);
+      println!("{} plays {}", if white { "White" } else { "Black" }, game.board().san(move_));
+      move_
+    } else {
+      match read_move(&game, &mut lines) {
+        Some(move_) => move_,
+        None => return,
+      }
+    };
+
+    game.play(move_);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{parse_args,parse_move,render};
+  use hybrid_chess::{Board,RuleSet};
+
+  #[test]
+  pub fn rendering() {
+    assert_eq!(render(&Board::fresh(), true).lines().collect::<Vec<_>>(), [
+      " 8 r n b q k b n r",
+      " 7 p p p p p p p p",
+      " 6 . . . . . . . .",
+      " 5 . . . . . . . .",
+      " 4 . . . . . . . .",
+      " 3 . . . . . . . .",
+      " 2 P P P P P P P P",
+      " 1 R N B Q K B N R",
+      "   a b c d e f g h",
+    ]);
+
+    // Columns widen to fit hybrids
+    let board = Board::from_fen("4k3/8/8/8/3c4/8/8/4K2[RN] w - -").unwrap();
+    let rendered = render(&board, false);
+    let lines = rendered.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], " 8 ·    ·    ·    ·    ♚    ·    ·    ·");
+    assert_eq!(lines[4], " 4 ·    ·    ·    c    ·    ·    ·    ·");
+    assert_eq!(lines[7], " 1 ·    ·    ·    ·    ♔    ·    ·    [RN]");
+    assert_eq!(lines[8], "   a    b    c    d    e    f    g    h");
+  }
+
+  #[test]
+  pub fn arguments() {
+    let args = |args: &[&str]| parse_args(&args.iter().map(|&a| a.to_owned()).collect::<Vec<_>>());
+
+    let options = args(&[]).unwrap();
+    assert!(!options.white_ai && options.black_ai);
+    let options = args(&["ai-vs-ai", "--ascii", "--rules", "pairs"]).unwrap();
+    assert!(options.white_ai && options.black_ai && options.ascii);
+    assert!(options.board == Board::with_rules(RuleSet::PAIRS));
+    assert!(args(&["--fen", "4k3/8/8/8/8/8/8/4K2[RN] b - -"]).is_ok());
+    assert_eq!(args(&["--fen", "8/8/8/8/8/8/8/8 w - -"]).err().unwrap(), "Can't start from this FEN: white has no king, black has no king");
+    assert!(args(&["--fen", "4k3/8/8/8/8/8/8/4K2[RN] b - -", "--rules", "pairs"]).is_ok());
+    assert!(args(&["--fen", "4k3/8/8/8/8/8/8/4K2[RBN] b - -", "--rules", "pairs"]).is_err());
+
+    assert!(args(&["chess"]).is_err());
+    assert!(args(&["--rules", "loose"]).is_err());
+    assert!(args(&["--fen"]).is_err());
+  }
+
+  #[test]
+  pub fn moves() {
+    let board = Board::fresh();
+    assert!(parse_move(&board, "g1f3").unwrap() == parse_move(&board, "Nf3").unwrap());
+    assert!(parse_move(&board, "e5").is_err());
+  }
+}
@@ -0,0 +1,269 @@
+use std::error::Error;
+use std::fmt;
+
+use super::{component_letter,letter_component,Board,Geometry,Move,MoveKind,Piece};
+
+// Standard algebraic notation, extended for hybrids:
+//
+//   [RN]d4     a rook+knight hybrid moving as a whole
+//   [RN]>Nf5   only the knight component moving, leaving the rook behind
+//   Nf3+R      a knight merging into the rook on f3
+//   Nf3+[RB]   ... or into a rook+bishop hybrid
+//   cd3+N      a pawn merging diagonally, with its file given as for a capture
+//
+// Component letters always come in KQRBNP order. A merge's `+` is always
+// followed by a piece, so it can't be confused with the check suffix.
+//
+// The long form is the origin and destination squares, UCI style, prefixed by
+// the component letter for split moves and followed by the promotion piece:
+// `e2e4`, `Nd4f5`, `a7a8q`. Castling is written the same way, unless the king
+// could also get there without castling or doesn't move at all, as can happen
+// in Chess960. Then it's `O-O` or `O-O-O`.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NotationError {
+  Empty,
+  NoSuchMove(String),
+}
+
+impl fmt::Display for NotationError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      NotationError::Empty => write!(f, "no move given"),
+      NotationError::NoSuchMove(ref move_) => write!(f, "'{}' isn't a legal move", move_),
+    }
+  }
+}
+
+impl Error for NotationError {}
+
+fn piece_letters(piece: Piece) -> String {
+  let letters = piece.components().map(component_letter).collect::<String>();
+
+  if piece.is_hybrid() { format!("[{}]", letters) } else { letters }
+}
+
+// The king always castles to the g- or c-file, or whichever files are as far
+// from the edge on a board of another width
+fn castle_san(move_: Move, geometry: Geometry) -> String {
+  if geometry.x(move_.to) > geometry.width() / 2 { "O-O".to_owned() } else { "O-O-O".to_owned() }
+}
+
+impl Board {
+  // The part of a move that says what is moving. Plain pawns don't get one.
+  fn mover(&self, move_: Move) -> String {
+    let piece = self.piece(move_.from);
+
+    match move_.component {
+      Some(component) => format!("{}>{}", piece_letters(piece), component_letter(component)),
+      None if piece.is_pawn() && !piece.is_hybrid() => String::new(),
+      None => piece_letters(piece),
+    }
+  }
+
+  // Everything but the check suffix
+  fn san_body(&self, move_: Move, legal_moves: &[Move]) -> String {
+    if move_.kind == MoveKind::Castle { return castle_san(move_, self.geometry); }
+
+    let mover = self.mover(move_);
+    let target = self.piece(move_.to);
+    let capture = self.captured_by(move_).is_some();
+    let mut san = mover.clone();
+
+    if mover.is_empty() {
+      // Pawns change file to capture or to merge diagonally
+      if self.geometry.x(move_.from) != self.geometry.x(move_.to) { san += &self.geometry.name(move_.from)[..1]; }
+    } else {
+      let others = legal_moves.iter()
+        .filter(|m| m.to == move_.to && m.from != move_.from && self.mover(**m) == mover)
+        .collect::<Vec<_>>();
+
+      if !others.is_empty() {
+        let from = self.geometry.name(move_.from);
+        if others.iter().all(|m| self.geometry.x(m.from) != self.geometry.x(move_.from)) {
+          san += &from[..1];
+        } else if others.iter().all(|m| self.geometry.y(m.from) != self.geometry.y(move_.from)) {
+          san += &from[1..];
+        } else {
+          san += &from;
+        }
+      }
+    }
+
+    if capture { san += "x"; }
+    san += &self.geometry.name(move_.to);
+
+    if let MoveKind::Promotion(promotion) = move_.kind {
+      san.push('=');
+      san.push(component_letter(promotion));
+    }
+
+    if !capture && !target.is_empty() {
+      san.push('+');
+      san += &piece_letters(target);
+    }
+
+    san
+  }
+
+  pub fn san(&self, move_: Move) -> String {
+    let white = self.piece(move_.from).is_white();
+    let mut san = self.san_body(move_, &self.legal_moves(white));
+
+    let after = self.play(move_);
+    if after.pieces(!white).any(|(_, p)| p.is_king()) {
+      if after.is_check_mate(!white) {
+        san.push('#');
+      } else if after.is_check(!white) {
+        san.push('+');
+      }
+    }
+
+    san
+  }
+
+  // For the side to move. Check suffixes and annotations like `!?` are
+  // optional and not checked.
+  pub fn parse_san(&self, san: &str) -> Result<Move, NotationError> {
+    let mut body = san.trim().trim_end_matches(&['!', '?'][..]);
+    if body.ends_with('+') || body.ends_with('#') { body = &body[..body.len() - 1]; }
+    // Castling written with zeroes, but only as the whole move, since a 0 can
+    // also be part of a rank like the 10th
+    let body = match body { "0-0" => "O-O", "0-0-0" => "O-O-O", body => body };
+
+    if body.is_empty() { return Err(NotationError::Empty); }
+
+    let legal_moves = self.legal_moves(self.white_turn);
+    legal_moves.iter().cloned()
+      .find(|&m| self.san_body(m, &legal_moves) == body)
+      .ok_or_else(|| NotationError::NoSuchMove(san.to_owned()))
+  }
+
+  pub fn lan(&self, move_: Move) -> String {
+    if move_.kind == MoveKind::Castle {
+      let king = self.piece(move_.from);
+      if move_.from == move_.to || self.piece_moves_from(move_.from, king).contains(&move_.to) {
+        return castle_san(move_, self.geometry);
+      }
+    }
+
+    let mut lan = String::new();
+
+    if let Some(component) = move_.component { lan.push(component_letter(component)); }
+    lan += &self.geometry.name(move_.from);
+    lan += &self.geometry.name(move_.to);
+    if let MoveKind::Promotion(promotion) = move_.kind {
+      lan.push(component_letter(promotion).to_ascii_lowercase());
+    }
+
+    lan
+  }
+
+  pub fn parse_lan(&self, lan: &str) -> Result<Move, NotationError> {
+    let lan = lan.trim();
+    if lan.is_empty() { return Err(NotationError::Empty); }
+
+    let no_such_move = || NotationError::NoSuchMove(lan.to_owned());
+
+    if lan == "O-O" || lan == "O-O-O" {
+      return self.legal_moves(self.white_turn).into_iter()
+        .find(|&m| m.kind == MoveKind::Castle && self.lan(m) == lan)
+        .ok_or_else(no_such_move);
+    }
+
+    let (component, squares) = match lan.chars().next().filter(|c| c.is_ascii_uppercase()) {
+      Some(c) => (Some(letter_component(c).ok_or_else(no_such_move)?), &lan[1..]),
+      None => (None, lan),
+    };
+    let (from, squares) = self.geometry.parse_prefix(squares).ok_or_else(no_such_move)?;
+    let (to, promotion) = self.geometry.parse_prefix(squares).ok_or_else(no_such_move)?;
+    let promotion = match promotion {
+      "" => None,
+      p if p.len() == 1 => Some(letter_component(p.chars().next().unwrap()).ok_or_else(no_such_move)?),
+      _ => return Err(no_such_move()),
+    };
+
+    self.legal_moves(self.white_turn).into_iter()
+      .find(|&m| m.from == from && m.to == to && m.component == component && match m.kind {
+        MoveKind::Promotion(p) => promotion == Some(p),
+        MoveKind::Castle => promotion.is_none() && self.lan(m) == lan,
+        _ => promotion.is_none(),
+      })
+      .ok_or_else(no_such_move)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::NotationError;
+  use super::super::Board;
+
+  fn round_trip(fen: &str, lan: &str, san: &str) {
+    let board = Board::from_fen(fen).unwrap();
+    let move_ = board.parse_lan(lan).unwrap();
+
+    assert_eq!(board.san(move_), san);
+    assert!(board.parse_san(san).unwrap() == move_);
+    assert_eq!(board.lan(move_), lan);
+  }
+
+  #[test]
+  pub fn standard() {
+    let fresh = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -";
+    round_trip(fresh, "e2e4", "e4");
+    round_trip(fresh, "g1f3", "Nf3");
+
+    round_trip("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6", "e4d5", "exd5");
+    round_trip("4k3/8/8/8/8/8/8/4K2R w K -", "e1g1", "O-O");
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K2R w KQ -").unwrap();
+    assert!(board.parse_san("0-0").unwrap() == board.parse_san("O-O").unwrap());
+    assert!(board.parse_san("0-0-0+").unwrap() == board.parse_san("O-O-O").unwrap());
+    round_trip("4k3/P7/8/8/8/8/8/4K3 w - -", "a7a8q", "a8=Q+");
+    round_trip("4k3/P7/8/8/8/8/8/4K3 w - -", "a7a8n", "a8=N");
+    round_trip("7k/8/7K/8/8/8/8/6Q1 w - -", "g1g7", "Qg7#");
+    round_trip("7k/8/7K/8/8/8/8/6Q1 w - -", "g1g8", "Qg8+");
+    round_trip("4k3/8/8/8/8/8/4K3/R6R w - -", "a1d1", "Rad1");
+  }
+
+  #[test]
+  pub fn hybrids() {
+    let fresh = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -";
+    round_trip(fresh, "g1e2", "Ne2+P");
+
+    // Two pawns that can merge onto the same square
+    let fen = "4k3/8/8/8/8/3N4/2P1P3/4K3 w - -";
+    round_trip(fen, "c2d3", "cd3+N");
+    round_trip(fen, "e2d3", "ed3+N");
+    round_trip(fen, "e2e3", "e3");
+
+    let fen = "4k3/8/8/[RN]7/8/8/4K3/[RN]6[RB] w - -";
+    round_trip(fen, "a1a3", "[RN]1a3");
+    round_trip(fen, "a5a3", "[RN]5a3");
+    round_trip(fen, "Na1b3", "[RN]>N1b3");
+    round_trip(fen, "Na1c2", "[RN]>Nc2");
+    round_trip(fen, "e2f1", "Kf1");
+    round_trip(fen, "a1h1", "[RN]h1+[RB]");
+    round_trip(fen, "Ra1b1", "[RN]>Rb1");
+
+    let board = Board::from_fen(fen).unwrap();
+    assert!(board.parse_san("[RN]1a3+").is_ok());
+    assert_eq!(board.parse_san("[RN]a3").err(), Some(NotationError::NoSuchMove("[RN]a3".to_owned())));
+    assert_eq!(board.parse_san(" ").err(), Some(NotationError::Empty));
+    assert_eq!(board.parse_lan("a1a9").err(), Some(NotationError::NoSuchMove("a1a9".to_owned())));
+  }
+
+  #[test]
+  pub fn other_sizes() {
+    let capablanca = "rn[bn]bqkb[rn]nr/pppppppppp/10/10/10/10/PPPPPPPPPP/RN[BN]BQKB[RN]NR w KQkq -";
+    round_trip(capablanca, "i1j3", "Nj3");
+    round_trip(capablanca, "Nh1g3", "[RN]>Ng3");
+    round_trip("4k5/10/10/10/10/10/10/R3K5 w Q -", "e1c1", "O-O-O");
+
+    round_trip("4k3/P7/8/8/8/8/8/8/8/8/8/4K3 w - -", "a11a12q", "a12=Q+");
+
+    // Ranks with a zero in them
+    let tall = "4k3/8/8/4K3/8/8/8/8/8/8/R7/8 w - -";
+    round_trip(tall, "e9e10", "Ke10");
+    round_trip(tall, "a2a10", "Ra10");
+  }
+}
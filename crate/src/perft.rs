@@ -0,0 +1,102 @@
+use super::{Board,Move};
+
+// Counts the leaf nodes of the legal move tree, for checking the move
+// generator against known totals.
+
+impl Board {
+  // For the side to move
+  pub fn perft(&self, depth: u32) -> u64 {
+    self.clone().count_nodes(depth)
+  }
+
+  // The perft count below each legal move, for narrowing down where two
+  // generators disagree.
+  pub fn divide(&self, depth: u32) -> Vec<(Move, u64)> {
+    assert!(depth >= 1, "divide needs at least one ply");
+
+    let mut board = self.clone();
+    self.legal_moves(self.white_turn).into_iter()
+      .map(|m| {
+        let undo = board.make_move(m);
+        let nodes = board.count_nodes(depth - 1);
+        board.unmake_move(undo);
+        (m, nodes)
+      })
+      .collect()
+  }
+
+  fn count_nodes(&mut self, depth: u32) -> u64 {
+    if depth == 0 { return 1; }
+
+    let moves = self.legal_moves(self.white_turn);
+    if depth == 1 { return moves.len() as u64; }
+
+    moves.into_iter()
+      .map(|m| {
+        let undo = self.make_move(m);
+        let nodes = self.count_nodes(depth - 1);
+        self.unmake_move(undo);
+        nodes
+      })
+      .sum()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::super::{Board,Geometry,RuleSet};
+
+  // Merges are the only thing separating these rules from ordinary chess,
+  // apart from a piece moving onto a friendly one of the same type, which
+  // max_components can't rule out. The totals below match the published ones
+  // wherever that can't happen, and otherwise match an independent generator
+  // that allowed for it.
+  const SAME_TYPE_MERGES: RuleSet = RuleSet{ king_merges: false, pawn_merges: false, max_components: 1, splits: true };
+
+  const POSITIONS: [(&str, RuleSet, &[u64]); 12] = [
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -", SAME_TYPE_MERGES, &[20, 400, 8902]),
+    ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -", SAME_TYPE_MERGES, &[48, 2039]),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -", SAME_TYPE_MERGES, &[14, 191, 2812, 43238]),
+    ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq -", SAME_TYPE_MERGES, &[6, 264, 9467]),
+    ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ -", SAME_TYPE_MERGES, &[44, 1486, 62507]),
+
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -", RuleSet::CLASSIC, &[40, 1598, 77037]),
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -", RuleSet::NO_KING_MERGES, &[34, 1156, 48887]),
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -", RuleSet::PAIRS, &[40, 1598, 74351]),
+    ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -", RuleSet::STRICT, &[23, 529, 13480]),
+    ("r3k2r/pp[QB]2ppp/8/2[rn]1pP2/8/8/PPP2[NP]PP/R3K2R w Kq e6", RuleSet::CLASSIC, &[75, 3198]),
+    ("4k3/8/8/[RN]7/8/8/4K3/[RN]6[RB] w - -", RuleSet::CLASSIC, &[112, 480, 48090]),
+    ("[KQRBNP]7/8/8/8/8/8/8/6[kqrbnp]1 w - -", RuleSet::CLASSIC, &[80, 5945]),
+  ];
+
+  #[test]
+  pub fn reference_positions() {
+    for &(fen, rules, counts) in &POSITIONS {
+      let mut board = Board::from_fen(fen).unwrap();
+      board.rules = rules;
+
+      for (depth, &count) in counts.iter().enumerate() {
+        assert_eq!(board.perft(depth as u32 + 1), count, "{} at depth {}", fen, depth + 1);
+      }
+    }
+  }
+
+  // Only checked against this generator, to catch changes in it
+  #[test]
+  pub fn other_sizes() {
+    let mini = Board::starting(Geometry::MINI, RuleSet::CLASSIC).unwrap();
+    assert_eq!((1..4).map(|depth| mini.perft(depth)).collect::<Vec<_>>(), [32, 999, 37541]);
+
+    let wide = Board::starting(Geometry::WIDE, RuleSet::CLASSIC).unwrap();
+    assert_eq!((1..3).map(|depth| wide.perft(depth)).collect::<Vec<_>>(), [70, 4892]);
+  }
+
+  #[test]
+  pub fn divide() {
+    let board = Board::fresh();
+    let divide = board.divide(2);
+
+    assert_eq!(divide.len(), 40);
+    assert_eq!(divide.iter().map(|&(_, n)| n).sum::<u64>(), board.perft(2));
+  }
+}
@@ -0,0 +1,307 @@
+use std::error::Error;
+use std::fmt;
+
+use super::{Board,FenError,Game,GameResult,NotationError,RuleSet};
+
+// Moves are written in the extended SAN from `notation`. Games that don't
+// start from the usual position carry `SetUp` and `FEN` tags, and games with
+// non-default rules a `Rules` tag naming the preset.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PgnError {
+  Tag(String),
+  Fen(FenError),
+  Rules(String),
+  UnclosedComment,
+  UnclosedVariation,
+  // Ply numbers count from 1
+  Move { ply: usize, error: NotationError },
+  MoveAfterEnd(String),
+}
+
+impl fmt::Display for PgnError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      PgnError::Tag(ref line) => write!(f, "bad tag pair: {}", line),
+      PgnError::Fen(ref error) => write!(f, "bad FEN tag: {}", error),
+      PgnError::Rules(ref rules) => write!(f, "unknown rules '{}'", rules),
+      PgnError::UnclosedComment => write!(f, "unclosed comment"),
+      PgnError::UnclosedVariation => write!(f, "unclosed variation"),
+      PgnError::Move{ply, ref error} => write!(f, "move {}{}: {}", (ply - 1) / 2 + 1, if ply % 2 == 1 { "." } else { "..." }, error),
+      PgnError::MoveAfterEnd(ref move_) => write!(f, "'{}' comes after the end of the game", move_),
+    }
+  }
+}
+
+impl Error for PgnError {}
+
+impl From<FenError> for PgnError {
+  fn from(error: FenError) -> Self { PgnError::Fen(error) }
+}
+
+fn result_token(result: Option<GameResult>) -> &'static str {
+  match result {
+    Some(GameResult::Checkmate(true)) => "1-0",
+    Some(GameResult::Checkmate(false)) => "0-1",
+    Some(GameResult::Draw(_)) => "1/2-1/2",
+    None => "*",
+  }
+}
+
+fn parse_tag(line: &str) -> Option<(String, String)> {
+  let inner = line.trim().trim_start_matches('[').trim_end_matches(']');
+  let quote = inner.find('"')?;
+  let name = inner[..quote].trim();
+  let value = inner[quote..].trim();
+
+  if name.is_empty() || value.len() < 2 || !value.ends_with('"') { return None; }
+
+  let value = value[1..value.len() - 1].replace("\\\"", "\"").replace("\\\\", "\\");
+  Some((name.to_owned(), value))
+}
+
+// Splits movetext into moves, dropping comments, variations, move numbers
+// and annotation glyphs, and stopping at the result.
+fn tokens(movetext: &str) -> Result<Vec<String>, PgnError> {
+  let mut tokens = vec![];
+  let mut chars = movetext.chars().peekable();
+
+  while let Some(c) = chars.next() {
+    match c {
+      '{' => {
+        if !chars.by_ref().any(|c| c == '}') { return Err(PgnError::UnclosedComment); }
+      },
+      ';' => {
+        chars.by_ref().find(|&c| c == '\n');
+      },
+      '(' => {
+        let mut depth = 1;
+        while depth > 0 {
+          match chars.next() {
+            Some('(') => depth += 1,
+            Some(')') => depth -= 1,
+            Some(_) => {},
+            None => return Err(PgnError::UnclosedVariation),
+          }
+        }
+      },
+      c if c.is_whitespace() => {},
+      c => {
+        let mut token = c.to_string();
+        while let Some(&c) = chars.peek() {
+          if c.is_whitespace() || c == '{' || c == ';' || c == '(' { break; }
+          token.push(c);
+          chars.next();
+        }
+
+        if ["1-0", "0-1", "1/2-1/2", "*"].contains(&token.as_str()) { break; }
+        if token.starts_with('$') { continue; }
+
+        // Move numbers, possibly stuck to the move itself as in `1.e4`
+        let token = match token.rfind('.') {
+          Some(i) if token[..i].chars().all(|c| c.is_ascii_digit() || c == '.') => token[i + 1..].to_owned(),
+          _ => token,
+        };
+        if !token.is_empty() { tokens.push(token); }
+      },
+    }
+  }
+
+  Ok(tokens)
+}
+
+impl Game {
+  // Tags given here override the defaults, and any others are added after the
+  // standard seven.
+  pub fn to_pgn(&self, tags: &[(&str, &str)]) -> String {
+    let start_fen = self.start().to_fen();
+    let standard = start_fen == Board::fresh().to_fen();
+    let rules = self.start().rules;
+
+    let mut all_tags = vec![
+      ("Event", "Hybrid chess game".to_owned()),
+      ("Site", "?".to_owned()),
+      ("Date", "????.??.??".to_owned()),
+      ("Round", "-".to_owned()),
+      ("White", "?".to_owned()),
+      ("Black", "?".to_owned()),
+      ("Result", result_token(self.result()).to_owned()),
+      ("Variant", "Hybrid".to_owned()),
+    ];
+    if rules != RuleSet::default() {
+      if let Some(name) = rules.name() { all_tags.push(("Rules", name.to_owned())); }
+    }
+    if !standard {
+      all_tags.push(("SetUp", "1".to_owned()));
+      all_tags.push(("FEN", start_fen));
+    }
+    for &(name, value) in tags {
+      if name == "Result" { continue; }
+      match all_tags.iter().position(|&(n, _)| n == name) {
+        Some(i) => all_tags[i].1 = value.to_owned(),
+        None => all_tags.push((name, value.to_owned())),
+      }
+    }
+
+    let mut pgn = String::new();
+    for (name, value) in all_tags {
+      pgn += &format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\""));
+    }
+    pgn.push('\n');
+
+    let mut words = vec![];
+    let mut board = self.start().clone();
+    let black_first = if self.start().white_turn() { 0 } else { 1 };
+    for (i, &move_) in self.moves().iter().enumerate() {
+      let ply = i + black_first;
+      if ply % 2 == 0 {
+        words.push(format!("{}.", ply / 2 + 1));
+      } else if i == 0 {
+        words.push(format!("{}...", ply / 2 + 1));
+      }
+
+      words.push(board.san(move_));
+      board.make_move(move_);
+    }
+    match self.result() {
+      Some(GameResult::Checkmate(_)) | None => {},
+      Some(GameResult::Draw(draw)) => words.push(format!("{{{}}}", draw.description())),
+    }
+    words.push(result_token(self.result()).to_owned());
+
+    let mut line = String::new();
+    for word in words {
+      if !line.is_empty() && line.len() + 1 + word.len() > 80 {
+        pgn += &line;
+        pgn.push('\n');
+        line.clear();
+      }
+      if !line.is_empty() { line.push(' '); }
+      line += &word;
+    }
+    pgn += &line;
+    pgn.push('\n');
+
+    pgn
+  }
+
+  // Reads the first game. The result is worked out from the moves, so a
+  // Result tag claiming a resignation or agreed draw is ignored.
+  pub fn from_pgn(pgn: &str) -> Result<Self, PgnError> {
+    let mut tags = vec![];
+    let mut lines = pgn.lines().peekable();
+
+    while let Some(&line) = lines.peek() {
+      let line = line.trim();
+      if line.is_empty() {
+        lines.next();
+      } else if line.starts_with('[') && line.ends_with(']') && line.contains('"') {
+        tags.push(parse_tag(line).ok_or_else(|| PgnError::Tag(line.to_owned()))?);
+        lines.next();
+      } else {
+        break;
+      }
+    }
+    let movetext = lines.collect::<Vec<_>>().join("\n");
+
+    let tag = |name: &str| tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+
+    let rules = match tag("Rules") {
+      Some(name) => RuleSet::preset(name).ok_or_else(|| PgnError::Rules(name.to_owned()))?,
+      None => RuleSet::default(),
+    };
+    let mut board = match tag("FEN") {
+      Some(fen) => Board::from_fen(fen)?,
+      None => Board::fresh(),
+    };
+    board.rules = rules;
+
+    let white_turn = board.white_turn();
+    let mut game = Game::from_board(board, white_turn);
+
+    for (i, token) in tokens(&movetext)?.into_iter().enumerate() {
+      if game.result().is_some() { return Err(PgnError::MoveAfterEnd(token)); }
+
+      let ply = i + if white_turn { 1 } else { 2 };
+      let move_ = game.board().parse_san(&token).map_err(|error| PgnError::Move{ply, error})?;
+      game.play(move_);
+    }
+
+    Ok(game)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::PgnError;
+  use super::super::{Board,Draw,Game,GameResult,NotationError,RuleSet};
+
+  fn play(game: &mut Game, sans: &[&str]) {
+    for san in sans {
+      let move_ = game.board().parse_san(san).unwrap();
+      game.play(move_);
+    }
+  }
+
+  #[test]
+  pub fn round_trip() {
+    let mut game = Game::new(RuleSet::PAIRS);
+    play(&mut game, &["e4", "d5", "exd5", "Nf6", "Nf3", "Nxd5", "Nbd2+P", "Nc6"]);
+
+    let pgn = game.to_pgn(&[("White", "Human"), ("Black", "AI"), ("Annotator", "Someone \"quoted\"")]);
+    assert!(pgn.starts_with("[Event \"Hybrid chess game\"]\n"));
+    assert!(pgn.contains("[White \"Human\"]\n[Black \"AI\"]\n[Result \"*\"]\n[Variant \"Hybrid\"]\n[Rules \"pairs\"]\n"));
+    assert!(pgn.contains("[Annotator \"Someone \\\"quoted\\\"\"]\n"));
+    assert!(pgn.ends_with("\n\n1. e4 d5 2. exd5 Nf6 3. Nf3 Nxd5 4. Nbd2+P Nc6 *\n"));
+
+    let imported = Game::from_pgn(&pgn).unwrap();
+    assert!(imported.board() == game.board());
+    assert!(imported.moves() == game.moves());
+  }
+
+  #[test]
+  pub fn set_up_position() {
+    let board = Board::from_fen("7k/8/6K1/8/8/8/8/Q7 b - -").unwrap();
+    let mut game = Game::from_board(board, false);
+    play(&mut game, &["Kg8", "Qa8#"]);
+    assert!(game.result() == Some(GameResult::Checkmate(true)));
+
+    let pgn = game.to_pgn(&[]);
+    assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"7k/8/6K1/8/8/8/8/Q7 b - -\"]\n"));
+    assert!(pgn.ends_with("\n\n1... Kg8 2. Qa8# 1-0\n"));
+
+    let imported = Game::from_pgn(&pgn).unwrap();
+    assert!(imported.board() == game.board());
+    assert!(imported.result() == game.result());
+  }
+
+  #[test]
+  pub fn tall_board() {
+    let game = Game::from_pgn("[FEN \"4k3/8/8/4K3/8/8/8/8/8/8/R7/8 w - -\"]\n1. Ke10 Kd12 2. Ra10").unwrap();
+    assert!(game.board() == &Board::from_fen("3k4/8/R3K3/8/8/8/8/8/8/8/8/8 b - -").unwrap());
+    assert!(game.to_pgn(&[]).ends_with("\n\n1. Ke10 Kd12 2. Ra10 *\n"));
+  }
+
+  #[test]
+  pub fn draw() {
+    let mut game = Game::new(RuleSet::default());
+    play(&mut game, &["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1", "Ng8"]);
+
+    let pgn = game.to_pgn(&[]);
+    assert!(pgn.ends_with("4. Ng1 Ng8 {Draw by threefold repetition}\n1/2-1/2\n"));
+    assert!(Game::from_pgn(&pgn).unwrap().result() == Some(GameResult::Draw(Draw::ThreefoldRepetition)));
+  }
+
+  #[test]
+  pub fn reading() {
+    let pgn = "[Event \"Casual\"]\n[White \"A\"]\n\n1.e4 {best by test} e5 $1 (1... c5 2. Nf3) 2. Nf3 ; a comment\nNc6 3.Nbd2+P?! 1-0\n";
+    let game = Game::from_pgn(pgn).unwrap();
+    assert_eq!(game.moves().len(), 5);
+    assert!(game.result().is_none());
+
+    assert_eq!(Game::from_pgn("1. e4 e4").err(), Some(PgnError::Move{ply: 2, error: NotationError::NoSuchMove("e4".to_owned())}));
+    assert_eq!(Game::from_pgn("1. e4 {oops").err(), Some(PgnError::UnclosedComment));
+    assert_eq!(Game::from_pgn("[Rules \"anything\"]\n1. e4").err(), Some(PgnError::Rules("anything".to_owned())));
+    assert_eq!(Game::from_pgn("[FEN \"7k/8/7K/8/8/8/8/6Q1 w - -\"]\n1. Qg7# Kg8").err(), Some(PgnError::MoveAfterEnd("Kg8".to_owned())));
+  }
+}
@@ -0,0 +1,103 @@
+use super::{KING,QUEEN,ROOK,BISHOP,KNIGHT,PAWN,CAMEL,ZEBRA,NIGHTRIDER};
+
+// Every kind of component piece and how it moves. A hybrid moves like all of
+// its components together. Kings and pawns also have rules of their own
+// beyond their movement, for check, castling, promotion and en passant.
+
+pub const ORTHOGONAL: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+pub const DIAGONAL: [(i32, i32); 4] = [(1, 1), (-1, 1), (1, -1), (-1, -1)];
+pub const KNIGHT_DS: [(i32, i32); 8] = [(1, 2), (2, 1),
+                                        (-1, 2), (2, -1),
+                                        (1, -2), (-2, 1),
+                                        (-1, -2), (-2, -1)];
+const CAMEL_DS: [(i32, i32); 8] = [(1, 3), (3, 1), (-1, 3), (3, -1), (1, -3), (-3, 1), (-1, -3), (-3, -1)];
+const ZEBRA_DS: [(i32, i32); 8] = [(2, 3), (3, 2), (-2, 3), (3, -2), (2, -3), (-3, 2), (-2, -3), (-3, -2)];
+
+// The steps of a pattern are all its steps' reflections, so a piece on one
+// square attacks another exactly when a piece on the other would attack it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Movement {
+  // Jumps straight to any square one step away
+  Leap(&'static [(i32, i32)]),
+  // Repeats a step until it reaches the edge of the board or another piece
+  Ride(&'static [(i32, i32)]),
+  // Forward onto an empty square, two from its starting row, and diagonally
+  // forward to capture
+  Pawn,
+}
+
+pub struct PieceType {
+  pub component: u16,
+  pub letter: char,
+  // Also the CSS class for drawing it, so unused without the browser
+  // interface
+  #[cfg_attr(not(any(feature = "web", feature = "serde")), allow(dead_code))]
+  pub name: &'static str,
+  pub movements: &'static [Movement],
+  // How much the AI thinks it's worth
+  pub value: i32,
+  // Taken off a hybrid's value for each other component it has whose moves
+  // this one already covers, like a rook's for a queen
+  pub overlaps: &'static [(u16, i32)],
+}
+
+// In the order their letters are written in hybrids
+pub const REGISTRY: [PieceType; 9] = [
+  PieceType{ component: KING, letter: 'K', name: "king", movements: &[Movement::Leap(&ORTHOGONAL), Movement::Leap(&DIAGONAL)], value: 1000, overlaps: &[] },
+  PieceType{ component: QUEEN, letter: 'Q', name: "queen", movements: &[Movement::Ride(&ORTHOGONAL), Movement::Ride(&DIAGONAL)], value: 100, overlaps: &[(ROOK, 20), (BISHOP, 20), (PAWN, 10)] },
+  PieceType{ component: ROOK, letter: 'R', name: "rook", movements: &[Movement::Ride(&ORTHOGONAL)], value: 70, overlaps: &[] },
+  PieceType{ component: BISHOP, letter: 'B', name: "bishop", movements: &[Movement::Ride(&DIAGONAL)], value: 50, overlaps: &[] },
+  PieceType{ component: KNIGHT, letter: 'N', name: "knight", movements: &[Movement::Leap(&KNIGHT_DS)], value: 60, overlaps: &[] },
+  PieceType{ component: PAWN, letter: 'P', name: "pawn", movements: &[Movement::Pawn], value: 20, overlaps: &[] },
+
+  PieceType{ component: CAMEL, letter: 'C', name: "camel", movements: &[Movement::Leap(&CAMEL_DS)], value: 40, overlaps: &[] },
+  PieceType{ component: ZEBRA, letter: 'Z', name: "zebra", movements: &[Movement::Leap(&ZEBRA_DS)], value: 40, overlaps: &[] },
+  PieceType{ component: NIGHTRIDER, letter: 'H', name: "nightrider", movements: &[Movement::Ride(&KNIGHT_DS)], value: 80, overlaps: &[(KNIGHT, 20)] },
+];
+
+pub fn piece_type(component: u16) -> &'static PieceType {
+  REGISTRY.iter().find(|t| t.component == component).expect("Not a component")
+}
+
+#[cfg(test)]
+mod test {
+  use super::REGISTRY;
+  use super::super::{Board,Loc,COLOR_MASK};
+  use super::super::geometry::loc;
+
+  fn names(board: &Board, locs: Vec<Loc>) -> Vec<String> {
+    locs.into_iter().map(|loc| board.geometry().name(loc)).collect()
+  }
+
+  #[test]
+  pub fn registry() {
+    let mut all = 0;
+    for (i, piece_type) in REGISTRY.iter().enumerate() {
+      assert_eq!(piece_type.component.count_ones(), 1);
+      assert!(piece_type.component & (all | COLOR_MASK) == 0);
+      assert!(piece_type.letter.is_ascii_uppercase());
+      assert!(REGISTRY[..i].iter().all(|t| t.letter != piece_type.letter));
+      all |= piece_type.component;
+    }
+  }
+
+  #[test]
+  pub fn fairy_pieces() {
+    let board = Board::from_fen("4k3/8/8/8/3C4/8/1P6/4K2Z w - -").unwrap();
+    assert_eq!(names(&board, board.moves_from(loc("d4")).unwrap()), ["c7", "e7", "a5", "g5", "a3", "g3", "c1", "e1"]);
+    assert_eq!(names(&board, board.moves_from(loc("h1")).unwrap()), ["f4", "e3"]);
+    assert_eq!(names(&board, board.attackers_of(loc("e3"), true)), ["h1"]);
+
+    // The nightrider keeps going until something's in the way, and merges
+    // with the camel on c5 rather than going past it
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/H3K3 w - -").unwrap();
+    assert_eq!(names(&board, board.moves_from(loc("a1")).unwrap()), ["d7", "c5", "g4", "b3", "e3", "c2"]);
+    let board = Board::from_fen("4k3/8/8/2C5/8/8/8/H3K3 w - -").unwrap();
+    assert_eq!(names(&board, board.moves_from(loc("a1")).unwrap()), ["c5", "g4", "b3", "e3", "c2"]);
+
+    let board = Board::from_fen("8/4k3/8/8/3C4/1N6/8/4K3 w - -").unwrap();
+    let merge = board.parse_san("Nd4+C").unwrap();
+    assert_eq!(board.play(merge).to_fen(), "8/4k3/8/8/3[NC]4/8/8/4K3 b - -");
+    assert!(board.play(merge).is_check(false));
+  }
+}
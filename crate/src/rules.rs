@@ -0,0 +1,51 @@
+use super::Piece;
+use super::registry::REGISTRY;
+#[cfg(feature = "serde")]
+use serde::{Serialize,Deserialize};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RuleSet {
+  pub king_merges: bool,
+  pub pawn_merges: bool,
+  // Most component pieces a single hybrid may be made of
+  pub max_components: u32,
+  pub splits: bool,
+}
+
+impl RuleSet {
+  // No limit, so a hybrid can take in every kind of piece there is
+  pub const CLASSIC: RuleSet = RuleSet{ king_merges: true, pawn_merges: true, max_components: REGISTRY.len() as u32, splits: true };
+  pub const NO_KING_MERGES: RuleSet = RuleSet{ king_merges: false, ..RuleSet::CLASSIC };
+  pub const PAIRS: RuleSet = RuleSet{ max_components: 2, ..RuleSet::CLASSIC };
+  pub const STRICT: RuleSet = RuleSet{ king_merges: false, pawn_merges: false, max_components: 2, splits: true };
+
+  pub fn preset(name: &str) -> Option<Self> {
+    match name {
+      "classic" => Some(RuleSet::CLASSIC),
+      "no-king-merges" => Some(RuleSet::NO_KING_MERGES),
+      "pairs" => Some(RuleSet::PAIRS),
+      "strict" => Some(RuleSet::STRICT),
+      _ => None,
+    }
+  }
+
+  // The inverse of `preset`
+  pub fn name(&self) -> Option<&'static str> {
+    ["classic", "no-king-merges", "pairs", "strict"].iter().cloned()
+      .find(|&name| RuleSet::preset(name) == Some(*self))
+  }
+
+  pub(crate) fn allows_merge(&self, piece: Piece, other: Piece) -> bool {
+    let merged = piece.merge(other);
+
+    if !self.king_merges && merged.is_king() { return false; }
+    if !self.pawn_merges && merged.is_pawn() { return false; }
+
+    merged.components().count() as u32 <= self.max_components
+  }
+}
+
+impl Default for RuleSet {
+  fn default() -> Self { RuleSet::CLASSIC }
+}
@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize,Deserializer,Serialize,Serializer};
+use serde::de::Error as DeError;
+use serde::ser::Error as SerError;
+
+use super::{Board,Game,Geometry,Loc,Piece,RuleSet,BLACK,WHITE};
+use super::registry::REGISTRY;
+
+// A readable JSON form, behind the `serde` feature. Squares are written by
+// name and pieces as their color and a list of component names:
+//
+//   {"white": true, "components": ["rook", "knight"]}
+//
+// A board lists only its occupied squares, and a game is its starting
+// position and moves in long algebraic notation, which get replayed when
+// it's read back in.
+
+#[derive(Serialize, Deserialize)]
+struct PieceRecord {
+  white: bool,
+  // In registry order. None at all for an empty square.
+  components: Vec<String>,
+}
+
+impl Serialize for Piece {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    PieceRecord{
+      white: self.is_white(),
+      components: REGISTRY.iter()
+        .filter(|t| self.0 & t.component != 0)
+        .map(|t| t.name.to_owned())
+        .collect(),
+    }.serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for Piece {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let record = PieceRecord::deserialize(deserializer)?;
+    if record.components.is_empty() { return Ok(Piece::EMPTY); }
+
+    let mut piece = Piece(if record.white { WHITE } else { BLACK });
+    for name in &record.components {
+      let piece_type = REGISTRY.iter().find(|t| t.name == name)
+        .ok_or_else(|| D::Error::custom(format!("'{}' isn't a kind of piece", name)))?;
+      piece.0 |= piece_type.component;
+    }
+
+    Ok(piece)
+  }
+}
+
+// A square on its own has no board to say how big it is, so it's named as on
+// the standard one
+impl Serialize for Loc {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let geometry = Geometry::STANDARD;
+    if self.0 < 0 || self.0 as usize >= geometry.size() {
+      return Err(S::Error::custom("only squares on the standard board can be written on their own"));
+    }
+
+    geometry.name(*self).serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for Loc {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let name = String::deserialize(deserializer)?;
+
+    Geometry::STANDARD.parse(&name).ok_or_else(|| D::Error::custom(format!("'{}' isn't a square", name)))
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+struct BoardRecord {
+  width: usize,
+  height: usize,
+  pieces: BTreeMap<String, Piece>,
+  white_turn: bool,
+  // As in a FEN
+  castling: String,
+  en_passant: Option<String>,
+  rules: RuleSet,
+}
+
+impl Serialize for Board {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let geometry = self.geometry;
+
+    BoardRecord{
+      width: geometry.width(),
+      height: geometry.height(),
+      pieces: geometry.squares()
+        .filter(|&loc| !self.piece(loc).is_empty())
+        .map(|loc| (geometry.name(loc), self.piece(loc)))
+        .collect(),
+      white_turn: self.white_turn,
+      castling: self.castling_fen(),
+      en_passant: self.en_passant.map(|loc| geometry.name(loc)),
+      rules: self.rules,
+    }.serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for Board {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let record = BoardRecord::deserialize(deserializer)?;
+
+    let geometry = Geometry::new(record.width, record.height)
+      .ok_or_else(|| D::Error::custom(format!("a board can't be {}x{}", record.width, record.height)))?;
+    let mut board = Board::empty_with_geometry(geometry);
+    board.white_turn = record.white_turn;
+    board.rules = record.rules;
+
+    for (name, piece) in record.pieces {
+      let loc = geometry.parse(&name)
+        .ok_or_else(|| D::Error::custom(format!("'{}' isn't a square on a {}x{} board", name, geometry.width(), geometry.height())))?;
+      board.pieces[loc.0 as usize] = piece;
+    }
+
+    // Castling and en passant are checked the same way as for a FEN
+    board.set_castling_fen(&record.castling).map_err(D::Error::custom)?;
+    board.set_en_passant_fen(record.en_passant.as_ref().map_or("-", |name| name.as_str())).map_err(D::Error::custom)?;
+
+    board.hash = board.compute_hash();
+    Ok(board)
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+struct GameRecord {
+  start: Board,
+  moves: Vec<String>,
+}
+
+impl Serialize for Game {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut board = self.start().clone();
+    let moves = self.moves().iter().map(|&move_| {
+      let lan = board.lan(move_);
+      board.make_move(move_);
+      lan
+    }).collect();
+
+    GameRecord{ start: self.start().clone(), moves }.serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for Game {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let record = GameRecord::deserialize(deserializer)?;
+
+    let white_turn = record.start.white_turn();
+    let mut game = Game::from_board(record.start, white_turn);
+    for (i, lan) in record.moves.iter().enumerate() {
+      if game.result().is_some() {
+        return Err(D::Error::custom(format!("move {} comes after the game is over", i + 1)));
+      }
+
+      let move_ = game.board().parse_lan(lan).map_err(|error| D::Error::custom(format!("move {}: {}", i + 1, error)))?;
+      game.play(move_);
+    }
+
+    Ok(game)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use serde_json;
+
+  use super::super::{Board,Game,Geometry,Loc,Piece,RuleSet,WHITE,BLACK,ROOK,KNIGHT,CAMEL};
+
+  #[test]
+  pub fn pieces_and_squares() {
+    let piece = Piece(WHITE | ROOK | KNIGHT);
+    assert_eq!(serde_json::to_string(&piece).unwrap(), r#"{"white":true,"components":["rook","knight"]}"#);
+    assert!(serde_json::from_str::<Piece>(r#"{"white":false,"components":["camel"]}"#).unwrap() == Piece(BLACK | CAMEL));
+    assert!(serde_json::from_str::<Piece>(r#"{"white":false,"components":[]}"#).unwrap() == Piece::EMPTY);
+    assert!(serde_json::from_str::<Piece>(r#"{"white":false,"components":["wizard"]}"#).is_err());
+
+    let e4 = Geometry::STANDARD.parse("e4").unwrap();
+    assert_eq!(serde_json::to_string(&e4).unwrap(), r#""e4""#);
+    assert!(serde_json::from_str::<Loc>(r#""e4""#).unwrap() == e4);
+    assert!(serde_json::from_str::<Loc>(r#""j4""#).is_err());
+    assert!(serde_json::to_string(&Loc(64)).is_err());
+  }
+
+  #[test]
+  pub fn boards() {
+    let board = Board::from_fen("4k3/8/8/3pP3/8/8/8/R3K2[RN] w Qk d6").unwrap();
+    let json = serde_json::to_value(&board).unwrap();
+    assert_eq!(json["pieces"]["h1"]["components"], serde_json::json!(["rook", "knight"]));
+    assert_eq!(json["castling"], "Qk");
+    assert_eq!(json["en_passant"], "d6");
+    assert_eq!(json["rules"]["max_components"], 9);
+
+    let read = serde_json::from_value::<Board>(json).unwrap();
+    assert!(read == board);
+    assert!(read.hash() == board.hash());
+
+    let mut board = Board::starting(Geometry::WIDE, RuleSet::STRICT).unwrap();
+    board.set_white_turn(false);
+    assert!(serde_json::from_str::<Board>(&serde_json::to_string(&board).unwrap()).unwrap() == board);
+
+    let mut json = serde_json::to_value(Board::fresh()).unwrap();
+    json["castling"] = "KX".into();
+    assert!(serde_json::from_value::<Board>(json.clone()).is_err());
+    json["castling"] = "-".into();
+    json["width"] = 20.into();
+    assert!(serde_json::from_value::<Board>(json).is_err());
+  }
+
+  #[test]
+  pub fn games() {
+    let mut game = Game::new(RuleSet::default());
+    for san in &["f3", "e5", "g4", "Qh4#"] {
+      let move_ = game.board().parse_san(san).unwrap();
+      game.play(move_);
+    }
+
+    let json = serde_json::to_value(&game).unwrap();
+    assert_eq!(json["moves"], serde_json::json!(["f2f3", "e7e5", "g2g4", "d8h4"]));
+
+    let read = serde_json::from_value::<Game>(json.clone()).unwrap();
+    assert!(read.board() == game.board() && read.moves() == game.moves() && read.result() == game.result());
+
+    let mut json = json;
+    json["moves"][3] = "d8h5".into();
+    assert!(serde_json::from_value::<Game>(json.clone()).is_err());
+    json["moves"] = serde_json::json!(["f2f3", "e7e5", "g2g4", "d8h4", "a2a3"]);
+    assert!(serde_json::from_value::<Game>(json).is_err());
+  }
+}
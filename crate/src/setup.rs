@@ -0,0 +1,275 @@
+use super::{Board,Castle,Geometry,Loc,Piece,RuleSet,CASTLING_RIGHTS,WHITE,BLACK,COLOR_MASK,KING,QUEEN,ROOK,BISHOP,KNIGHT};
+
+// Starting positions with other back ranks. Black's back rank always mirrors
+// white's, and the pawns start on the rows in front of them.
+
+const STANDARD_BACK_RANK: [u16; 8] = [ROOK, KNIGHT, BISHOP, QUEEN, KING, BISHOP, KNIGHT, ROOK];
+// Capablanca's archbishop and chancellor are just hybrids here
+const WIDE_BACK_RANK: [u16; 10] = [ROOK, KNIGHT, BISHOP | KNIGHT, BISHOP, QUEEN, KING, BISHOP, ROOK | KNIGHT, KNIGHT, ROOK];
+// Los Alamos chess's, which does without bishops
+const MINI_BACK_RANK: [u16; 6] = [ROOK, KNIGHT, QUEEN, KING, KNIGHT, ROOK];
+
+pub const CHESS960_POSITIONS: u32 = 960;
+
+// Which two of the five squares left over once the bishops and queen are
+// placed get the knights.
+const KNIGHT_PAIRS: [(usize, usize); 10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+
+// Scharnagl's numbering, under which the standard back rank is 518.
+fn chess960_back_rank(number: u32) -> [u16; 8] {
+  assert!(number < CHESS960_POSITIONS, "Chess960 positions are numbered 0 to 959");
+
+  let mut rank = [0; 8];
+  let mut n = number as usize;
+  let empty = |rank: &[u16; 8]| (0..8).filter(|&x| rank[x] == 0).collect::<Vec<_>>();
+
+  // One bishop on each color
+  rank[n % 4 * 2 + 1] = BISHOP;
+  n /= 4;
+  rank[n % 4 * 2] = BISHOP;
+  n /= 4;
+
+  let queen = empty(&rank)[n % 6];
+  rank[queen] = QUEEN;
+  n /= 6;
+
+  let (a, b) = KNIGHT_PAIRS[n];
+  let left = empty(&rank);
+  rank[left[a]] = KNIGHT;
+  rank[left[b]] = KNIGHT;
+
+  // The king always ends up between the rooks
+  for (&x, &piece) in empty(&rank).iter().zip(&[ROOK, KING, ROOK]) {
+    rank[x] = piece;
+  }
+
+  rank
+}
+
+impl Board {
+  // The usual starting position for the standard, wide and mini boards
+  pub fn starting(geometry: Geometry, rules: RuleSet) -> Option<Self> {
+    let back_rank: &[u16] = match geometry {
+      Geometry::STANDARD => &STANDARD_BACK_RANK,
+      Geometry::WIDE => &WIDE_BACK_RANK,
+      Geometry::MINI => &MINI_BACK_RANK,
+      _ => return None,
+    };
+
+    Self::with_back_rank(geometry, back_rank, rules)
+  }
+
+  pub fn chess960(number: u32, rules: RuleSet) -> Self {
+    Self::with_back_rank(Geometry::STANDARD, &chess960_back_rank(number), rules).expect("Chess960 back ranks have a king")
+  }
+
+  // White's back rank from the a-file rightwards, each square given as
+  // component bits so that hybrids can start on the board too. It has to fill
+  // the width of the board and have exactly one king. A plain king can castle
+  // with the plain rook furthest out on either side of it.
+  pub fn with_back_rank(geometry: Geometry, back_rank: &[u16], rules: RuleSet) -> Option<Self> {
+    if back_rank.len() != geometry.width() { return None; }
+    if back_rank.iter().any(|&c| c & COLOR_MASK != 0) { return None; }
+    if back_rank.iter().filter(|&&c| c & KING != 0).count() != 1 { return None; }
+
+    let mut board = Board::empty_with_geometry(geometry);
+    board.rules = rules;
+
+    for (x, &components) in back_rank.iter().enumerate() {
+      if components != 0 {
+        board.pieces[geometry.loc(x, geometry.back_row(false)).0 as usize] = Piece(BLACK | components);
+        board.pieces[geometry.loc(x, geometry.back_row(true)).0 as usize] = Piece(WHITE | components);
+      }
+      board.pieces[geometry.loc(x, geometry.pawn_row(false)).0 as usize] = Piece::BLACK_PAWN;
+      board.pieces[geometry.loc(x, geometry.pawn_row(true)).0 as usize] = Piece::WHITE_PAWN;
+    }
+
+    for (i, &right) in CASTLING_RIGHTS.iter().enumerate() {
+      if let Some(castle) = board.outer_castle(right) {
+        board.castles[i] = castle;
+        board.castling |= castle.right;
+      }
+    }
+
+    board.hash = board.compute_hash();
+    Some(board)
+  }
+
+  // For editing a position by hand. Castling rights go once their king or
+  // rook is no longer where it started, and en passant is never possible
+  // right after an edit.
+  pub fn place(&mut self, loc: Loc, piece: Piece) {
+    self.pieces[loc.0 as usize] = piece;
+    self.en_passant = None;
+
+    for castle in self.castles.iter() {
+      let color = if castle.white { WHITE } else { BLACK };
+      if self.piece(castle.king_from) != Piece(color | KING) || self.piece(castle.rook_from) != Piece(color | ROOK) {
+        self.castling &= !castle.right;
+      }
+    }
+
+    self.hash = self.compute_hash();
+  }
+
+  fn back_rank_king(&self, white: bool) -> Option<Loc> {
+    let y = self.geometry.back_row(white);
+    let king = Piece(if white { WHITE } else { BLACK } | KING);
+
+    (0..self.geometry.width()).map(|x| self.geometry.loc(x, y)).find(|&loc| self.piece(loc) == king)
+  }
+
+  // What a plain K, Q, k or q castling right stands for: the plain king on
+  // its back rank with the plain rook furthest out on that side of it.
+  pub(crate) fn outer_castle(&self, right: u8) -> Option<Castle> {
+    let usual = Castle::usual(right, self.geometry);
+    let king = self.back_rank_king(usual.white)?;
+    let rook = Piece(if usual.white { WHITE } else { BLACK } | ROOK);
+    let (king_x, y) = (self.geometry.x(king), self.geometry.y(king));
+
+    let mut files = if self.geometry.x(usual.rook_from) > self.geometry.x(usual.king_from) {
+      ((king_x + 1)..self.geometry.width()).rev().collect::<Vec<_>>()
+    } else {
+      (0..king_x).collect::<Vec<_>>()
+    };
+    files.retain(|&x| self.piece(self.geometry.loc(x, y)) == rook);
+
+    files.first().map(|&x| Castle::new(right, king, self.geometry.loc(x, y), self.geometry))
+  }
+
+  // The castle with the rook on the given file, for rights written as a file
+  // letter. Which side it's on depends on where the king stands.
+  pub(crate) fn castle_with_rook(&self, white: bool, file: usize) -> Option<Castle> {
+    let king = self.back_rank_king(white)?;
+    let king_x = self.geometry.x(king);
+    if file == king_x || file >= self.geometry.width() { return None; }
+
+    let right = match (white, file > king_x) {
+      (true, true) => CASTLING_RIGHTS[0],
+      (true, false) => CASTLING_RIGHTS[1],
+      (false, true) => CASTLING_RIGHTS[2],
+      (false, false) => CASTLING_RIGHTS[3],
+    };
+    Some(Castle::new(right, king, self.geometry.loc(file, self.geometry.y(king)), self.geometry))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{chess960_back_rank,CHESS960_POSITIONS,STANDARD_BACK_RANK};
+  use super::super::{Board,Geometry,MoveKind,Piece,RuleSet,WHITE,KING,QUEEN,ROOK,BISHOP,KNIGHT};
+  use super::super::geometry::loc;
+
+  #[test]
+  pub fn numbering() {
+    assert!(chess960_back_rank(518) == STANDARD_BACK_RANK);
+    assert!(Board::chess960(518, RuleSet::default()) == Board::fresh());
+    assert_eq!(Board::chess960(0, RuleSet::default()).to_fen(), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq -");
+    assert_eq!(Board::chess960(959, RuleSet::default()).to_fen(), "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq -");
+
+    let mut ranks = (0..CHESS960_POSITIONS).map(chess960_back_rank).collect::<Vec<_>>();
+    for rank in &ranks {
+      let files = |component| (0..8).filter(|&x| rank[x] == component).collect::<Vec<_>>();
+      let bishops = files(BISHOP);
+      let rooks = files(ROOK);
+      let king = files(KING)[0];
+
+      assert!(bishops[0] % 2 != bishops[1] % 2);
+      assert!(rooks[0] < king && king < rooks[1]);
+    }
+    ranks.sort();
+    ranks.dedup();
+    assert_eq!(ranks.len(), CHESS960_POSITIONS as usize);
+  }
+
+  #[test]
+  pub fn custom_back_rank() {
+    assert!(Board::with_back_rank(Geometry::STANDARD, &[ROOK, KNIGHT, BISHOP, 0, 0, BISHOP, KNIGHT, ROOK], RuleSet::default()).is_none());
+    assert!(Board::with_back_rank(Geometry::STANDARD, &[KING, KING, 0, 0, 0, 0, 0, 0], RuleSet::default()).is_none());
+    assert!(Board::with_back_rank(Geometry::STANDARD, &[ROOK, KNIGHT, BISHOP, KING, BISHOP, KNIGHT, ROOK], RuleSet::default()).is_none());
+
+    // The hybrid can't castle, and the rook in the way on g1 stops the king
+    // reaching the outer one.
+    let board = Board::with_back_rank(Geometry::STANDARD, &[ROOK | KNIGHT, 0, 0, KING, 0, 0, ROOK, ROOK], RuleSet::default()).unwrap();
+    assert_eq!(board.to_fen(), "[rn]2k2rr/pppppppp/8/8/8/8/PPPPPPPP/[RN]2K2RR w Kk -");
+    assert!(Board::from_fen(&board.to_fen()).unwrap() == board);
+    assert!(board.castles[0].rook_from == loc("h1"));
+    assert!(!board.legal_moves(true).iter().any(|m| m.kind == MoveKind::Castle));
+  }
+
+  #[test]
+  pub fn other_sizes() {
+    let wide = Board::starting(Geometry::WIDE, RuleSet::default()).unwrap();
+    assert_eq!(wide.to_fen(), "rn[bn]bqkb[rn]nr/pppppppppp/10/10/10/10/PPPPPPPPPP/RN[BN]BQKB[RN]NR w KQkq -");
+    assert!(Board::from_fen(&wide.to_fen()).unwrap() == wide);
+
+    let mini = Board::starting(Geometry::MINI, RuleSet::default()).unwrap();
+    assert_eq!(mini.to_fen(), "rnqknr/pppppp/6/6/PPPPPP/RNQKNR w KQkq -");
+    assert!(Board::from_fen(&mini.to_fen()).unwrap() == mini);
+
+    assert!(Board::starting(Geometry::new(12, 12).unwrap(), RuleSet::default()).is_none());
+
+    // The king on f1 castles to i1 and j1's rook to h1
+    let board = Board::from_fen("4k5/10/10/10/10/10/10/5K3R w K -").unwrap();
+    assert!(board.geometry() == Geometry::WIDE);
+    let castle = board.parse_san("O-O").unwrap();
+    assert_eq!(board.lan(castle), "f1i1");
+    assert_eq!(board.play(castle).to_fen(), "4k5/10/10/10/10/10/10/7RK1 b - -");
+  }
+
+  #[test]
+  pub fn place() {
+    let mut board = Board::fresh().move_(loc("e2"), loc("e4"));
+    board.place(loc("g1"), Piece::EMPTY);
+    board.place(loc("h8"), Piece(WHITE | QUEEN | KNIGHT));
+    assert_eq!(board.to_fen(), "rnbqkbn[QN]/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKB1R b KQq -");
+    assert!(Board::from_fen(&board.to_fen()).unwrap() == board);
+
+    board.place(loc("e1"), Piece::EMPTY);
+    board.place(loc("e1"), Piece::WHITE_KING);
+    assert_eq!(board.to_fen(), "rnbqkbn[QN]/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKB1R b q -");
+  }
+
+  #[test]
+  pub fn chess960_castling() {
+    // The king castles past its rook on g1, and lands on a square it could
+    // also just merge into.
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/R4KR1 w KQ -").unwrap();
+    let moves = board.legal_moves(true);
+    let to_g1 = moves.iter().filter(|m| m.from == loc("f1") && m.to == loc("g1")).collect::<Vec<_>>();
+    assert_eq!(to_g1.len(), 2);
+
+    let castle = **to_g1.iter().find(|m| m.kind == MoveKind::Castle).unwrap();
+    assert_eq!(board.play(castle).to_fen(), "4k3/8/8/8/8/8/8/R4RK1 b - -");
+    assert_eq!(board.san(castle), "O-O");
+    assert_eq!(board.lan(castle), "O-O");
+    assert!(board.parse_lan("O-O").unwrap() == castle);
+    assert!(board.parse_lan("f1g1").unwrap().kind == MoveKind::Merge);
+    assert_eq!(board.play(board.parse_san("Kg1+R").unwrap()).to_fen(), "4k3/8/8/8/8/8/8/R5[KR]1 b - -");
+
+    // Queenside, with the king staying put and the rook hopping over it
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/1RK5 w Q -").unwrap();
+    let castle = board.parse_san("O-O-O").unwrap();
+    assert!(castle.from == castle.to);
+    assert_eq!(board.play(castle).to_fen(), "4k3/8/8/8/8/8/8/2KR4 b - -");
+
+    let mut scratch = board.clone();
+    let undo = scratch.make_move(castle);
+    scratch.unmake_move(undo);
+    assert!(scratch == board);
+  }
+
+  #[test]
+  pub fn shredder_fen() {
+    let board = Board::from_fen("rk2r3/8/8/8/8/8/8/RK1R3R w AHa -").unwrap();
+    assert_eq!(board.to_fen(), "rk2r3/8/8/8/8/8/8/RK1R3R w KQq -");
+
+    // With two rooks on the same side, the inner one goes by its file
+    let fen = "rk4rr/pppppppp/8/8/8/8/8/RK1R3R w DQg -";
+    let board = Board::from_fen(fen).unwrap();
+    assert!(board.castles[0].rook_from == loc("d1"));
+    assert!(board.castles[2].rook_from == loc("g8"));
+    assert_eq!(board.to_fen(), fen);
+    assert_eq!(board.play(board.parse_san("O-O").unwrap()).to_fen(), "rk4rr/pppppppp/8/8/8/8/8/R4RKR b g -");
+  }
+}
@@ -0,0 +1,177 @@
+use std::error::Error;
+use std::fmt;
+
+use super::{Board,Loc,MoveKind,Piece,COLOR_MASK,QUEEN};
+use super::registry::REGISTRY;
+
+// Checks for positions that can't come about in a game, such as ones set up
+// by hand or read from a FEN, and for moves that aren't allowed. Squares are
+// given by name.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+  MissingKing { white: bool },
+  ExtraKings { white: bool, count: usize },
+  // A pawn on either side's back rank would already have promoted, or could
+  // never have got there
+  PawnOnBackRank(String),
+  // The side that just moved left its king attacked
+  OpponentInCheck,
+  // Bits that aren't any component in the registry
+  UnknownComponents(String),
+  // A hybrid the rules wouldn't let anyone make
+  DisallowedHybrid(String),
+}
+
+impl fmt::Display for Problem {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let side = |white| if white { "white" } else { "black" };
+
+    match *self {
+      Problem::MissingKing{white} => write!(f, "{} has no king", side(white)),
+      Problem::ExtraKings{white, count} => write!(f, "{} has {} kings", side(white), count),
+      Problem::PawnOnBackRank(ref square) => write!(f, "the pawn on {} is on a back rank", square),
+      Problem::OpponentInCheck => write!(f, "the side not to move is in check"),
+      Problem::UnknownComponents(ref square) => write!(f, "the piece on {} isn't made of known pieces", square),
+      Problem::DisallowedHybrid(ref square) => write!(f, "the rules don't allow the hybrid on {}", square),
+    }
+  }
+}
+
+impl Error for Problem {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MoveError {
+  OffBoard,
+  EmptySquare(String),
+  // The piece on this square belongs to the side not to move
+  NotYourTurn(String),
+  // Written as in long algebraic notation
+  Illegal(String),
+}
+
+impl fmt::Display for MoveError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      MoveError::OffBoard => write!(f, "that square isn't on the board"),
+      MoveError::EmptySquare(ref square) => write!(f, "there's no piece on {}", square),
+      MoveError::NotYourTurn(ref square) => write!(f, "the piece on {} can't move on this turn", square),
+      MoveError::Illegal(ref move_) => write!(f, "'{}' isn't a legal move", move_),
+    }
+  }
+}
+
+impl Error for MoveError {}
+
+impl Board {
+  // Everything wrong with the position, or nothing for one that could have
+  // come up in a game
+  pub fn validate(&self) -> Vec<Problem> {
+    let mut problems = vec![];
+
+    for &white in &[true, false] {
+      let count = self.pieces(white).filter(|&(_, p)| p.is_king()).count();
+      match count {
+        0 => problems.push(Problem::MissingKing{white}),
+        1 => {},
+        _ => problems.push(Problem::ExtraKings{white, count}),
+      }
+    }
+
+    let known = REGISTRY.iter().fold(COLOR_MASK, |mask, t| mask | t.component);
+    for (loc, piece) in self.pieces(true).chain(self.pieces(false)) {
+      let square = self.geometry.name(loc);
+      let y = self.geometry.y(loc);
+
+      if piece.0 & !known != 0 {
+        problems.push(Problem::UnknownComponents(square));
+        continue;
+      }
+      if piece.is_pawn() && (y == self.geometry.back_row(true) || y == self.geometry.back_row(false)) {
+        problems.push(Problem::PawnOnBackRank(square.clone()));
+      }
+      if piece.is_hybrid() && !self.rules.allows_merge(Piece(piece.color()), piece) {
+        problems.push(Problem::DisallowedHybrid(square));
+      }
+    }
+
+    if self.is_check(!self.white_turn) { problems.push(Problem::OpponentInCheck); }
+
+    problems
+  }
+
+  // Like `move_`, but only for legal moves by the side to move. Pawns reaching
+  // the end promote to queens.
+  pub fn try_move(&self, from: Loc, to: Loc) -> Result<Self, MoveError> {
+    if [from, to].iter().any(|loc| loc.0 < 0 || loc.0 as usize >= self.geometry.size()) {
+      return Err(MoveError::OffBoard);
+    }
+
+    let piece = self.piece(from);
+    if piece.is_empty() { return Err(MoveError::EmptySquare(self.geometry.name(from))); }
+    if piece.is_white() != self.white_turn { return Err(MoveError::NotYourTurn(self.geometry.name(from))); }
+
+    // Castling only when the king can't get there otherwise
+    let mut moves = self.legal_moves(self.white_turn);
+    moves.retain(|m| m.from == from && m.to == to && m.component.is_none());
+    moves.sort_by_key(|m| m.kind == MoveKind::Castle);
+
+    moves.into_iter()
+      .find(|m| match m.kind {
+        MoveKind::Promotion(promotion) => promotion == QUEEN,
+        _ => true,
+      })
+      .map(|m| self.play(m))
+      .ok_or_else(|| MoveError::Illegal(format!("{}{}", self.geometry.name(from), self.geometry.name(to))))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{MoveError,Problem};
+  use super::super::{Board,Loc,Piece,RuleSet,KING,QUEEN,ROOK,WHITE};
+  use super::super::geometry::loc;
+
+  #[test]
+  pub fn problems() {
+    assert!(Board::fresh().validate().is_empty());
+
+    let board = Board::from_fen("8/8/8/8/8/8/8/8 w - -").unwrap();
+    assert_eq!(board.validate(), [Problem::MissingKing{white: true}, Problem::MissingKing{white: false}]);
+    assert!(!board.is_check(true));
+    assert!(board.legal_moves(true).is_empty());
+
+    let board = Board::from_fen("P3k2k/8/8/8/8/8/8/4K2p b - -").unwrap();
+    assert_eq!(board.validate(), [
+      Problem::ExtraKings{white: false, count: 2},
+      Problem::PawnOnBackRank("a8".to_owned()),
+      Problem::PawnOnBackRank("h1".to_owned()),
+    ]);
+
+    let board = Board::from_fen("4k3/8/8/8/8/8/8/4R2K w - -").unwrap();
+    assert_eq!(board.validate(), [Problem::OpponentInCheck]);
+
+    let mut board = Board::from_fen("4k3/8/8/8/8/8/8/[KQ]7 w - -").unwrap();
+    board.rules = RuleSet::NO_KING_MERGES;
+    assert_eq!(board.validate(), [Problem::DisallowedHybrid("a1".to_owned())]);
+
+    board.pieces[loc("d4").0 as usize] = Piece(WHITE | ROOK | 0x4000);
+    assert!(board.validate().contains(&Problem::UnknownComponents("d4".to_owned())));
+  }
+
+  #[test]
+  pub fn try_move() {
+    let board = Board::fresh();
+    assert!(board.try_move(loc("e2"), loc("e4")).unwrap() == board.move_(loc("e2"), loc("e4")));
+    assert_eq!(board.try_move(loc("e3"), loc("e4")).err(), Some(MoveError::EmptySquare("e3".to_owned())));
+    assert_eq!(board.try_move(loc("e7"), loc("e5")).err(), Some(MoveError::NotYourTurn("e7".to_owned())));
+    assert_eq!(board.try_move(loc("e2"), loc("e5")).err(), Some(MoveError::Illegal("e2e5".to_owned())));
+    assert_eq!(board.try_move(loc("e2"), Loc(64)).err(), Some(MoveError::OffBoard));
+
+    // Promotes to a queen, and castles only when the king can't just move
+    let board = Board::from_fen("4k3/P7/8/8/8/8/8/4K2R w K -").unwrap();
+    assert!(board.try_move(loc("a7"), loc("a8")).unwrap().piece(loc("a8")) == Piece(WHITE | QUEEN));
+    let castled = board.try_move(loc("e1"), loc("g1")).unwrap();
+    assert!(castled.piece(loc("g1")) == Piece(WHITE | KING) && castled.piece(loc("f1")) == Piece(WHITE | ROOK));
+  }
+}
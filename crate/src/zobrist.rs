@@ -0,0 +1,42 @@
+use super::{Loc,Piece};
+
+// Keys are derived on demand rather than looked up, since a table covering
+// every hybrid on every square would be far too big for wasm.
+fn key(index: u64) -> u64 {
+  // splitmix64
+  let mut z = index.wrapping_add(1).wrapping_mul(0x9E3779B97F4A7C15);
+  z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+  z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+  z ^ (z >> 31)
+}
+
+// Enough for the biggest board a `Geometry` allows, and every value a
+// `Piece` could have
+const SQUARES: u64      = 16 * 16;
+const PIECE_VALUES: u64 = 1 << 16;
+
+const PIECES: u64     = 0;
+const SIDE: u64       = PIECES + SQUARES * PIECE_VALUES;
+const CASTLING: u64   = SIDE + 1;
+const EN_PASSANT: u64 = CASTLING + 16;
+
+pub fn piece(loc: Loc, piece: Piece) -> u64 {
+  if piece.is_empty() { return 0; }
+
+  key(PIECES + loc.0 as u64 * PIECE_VALUES + piece.0 as u64)
+}
+
+pub fn black_to_move() -> u64 {
+  key(SIDE)
+}
+
+pub fn castling(rights: u8) -> u64 {
+  key(CASTLING + rights as u64)
+}
+
+pub fn en_passant(loc: Option<Loc>) -> u64 {
+  match loc {
+    Some(loc) => key(EN_PASSANT + loc.0 as u64),
+    None => 0,
+  }
+}